@@ -0,0 +1,31 @@
+// Timings of the hot paths from simulate::bench, run with
+//
+//     cargo bench --bench hot_paths [-- <size>...]
+//
+// Population sizes default to 100, 400 and 1600.
+
+use simulate::bench::run_benchmarks;
+
+const STEPS: usize = 200;
+
+fn main() {
+    // cargo passes --bench to harnessless targets; only the sizes are ours
+    let mut sizes: Vec<usize> = std::env::args()
+        .skip(1)
+        .filter_map(|x| x.parse().ok())
+        .collect();
+    if sizes.is_empty() {
+        sizes = vec![100, 400, 1600];
+    }
+    match run_benchmarks(&sizes, STEPS) {
+        Ok(timings) => {
+            for timing in timings {
+                println!("{timing}");
+            }
+        }
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,573 @@
+use std::collections::HashMap;
+use std::f64::consts::PI;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use ndarray::Array2;
+
+use crate::history::Checkpoint;
+use crate::{Event, Population, SimError, SimulationConfig, Species};
+
+pub fn pair_correlation(population: &Population, max_r: f64, bins: usize) -> Vec<f64> {
+    // Pair correlation function g(r) over `bins` equal annuli up to `max_r`, on the unit torus
+
+    let n = population.size;
+    let mut counts = vec![0.0; bins];
+    if n < 2 || bins == 0 {
+        return counts;
+    }
+
+    let width = max_r / bins as f64;
+    for i in 0..n {
+        for j in (i + 1)..n {
+            let first = &population.individuals[i];
+            let second = &population.individuals[j];
+            let bin = (first.distance(second) / width) as usize;
+            if bin < bins {
+                counts[bin] += 2.0;
+            }
+        }
+    }
+
+    // the domain has unit area, so the intensity of ordered pairs is n(n - 1)
+    let pairs = (n * (n - 1)) as f64;
+    for (k, count) in counts.iter_mut().enumerate() {
+        let inner = k as f64 * width;
+        let outer = inner + width;
+        *count /= pairs * PI * (outer.powi(2) - inner.powi(2));
+    }
+    counts
+}
+
+pub struct PcfEnvelope {
+    pub radii: Vec<f64>,
+    pub mean: Vec<f64>,
+    pub lower: Vec<f64>,
+    pub upper: Vec<f64>,
+}
+
+pub fn pcf_envelope(
+    config: &SimulationConfig,
+    seeds: &[u64],
+    max_r: f64,
+    bins: usize,
+    level: f64,
+) -> Result<PcfEnvelope, SimError> {
+    // Run one replicate of the configured scenario per seed up to its max_t and
+    // summarise their pair correlation functions by the pointwise mean and the central
+    // `level` quantile band
+
+    let curves = replicates(seeds, |seed| {
+        let config = SimulationConfig {
+            seed: Some(seed),
+            ..config.clone()
+        };
+        let mut population = Population::from_config(&config)?;
+        population.simulate(config.max_t)?;
+        Ok(pair_correlation(&population, max_r, bins))
+    })?;
+
+    let width = max_r / bins as f64;
+    let mut envelope = PcfEnvelope {
+        radii: (0..bins).map(|k| (k as f64 + 0.5) * width).collect(),
+        mean: vec![],
+        lower: vec![],
+        upper: vec![],
+    };
+    for k in 0..bins {
+        let mut values: Vec<f64> = curves.iter().map(|curve| curve[k]).collect();
+        values.sort_by(|a, b| a.total_cmp(b));
+        envelope
+            .mean
+            .push(values.iter().sum::<f64>() / values.len().max(1) as f64);
+        envelope.lower.push(quantile(&values, (1.0 - level) / 2.0));
+        envelope.upper.push(quantile(&values, (1.0 + level) / 2.0));
+    }
+    Ok(envelope)
+}
+
+pub fn replicates<I: Copy + Sync, T: Send>(
+    items: &[I],
+    run: impl Fn(I) -> Result<T, SimError> + Sync,
+) -> Result<Vec<T>, SimError> {
+    // Run one replicate per item, usually a seed, on a pool of threads, one per core,
+    // each taking the next item as it finishes the last; results are in item order, so
+    // they do not depend on the number of threads. The first failure stops the
+    // remaining items from being started
+
+    let threads = thread::available_parallelism()
+        .map_or(1, |x| x.get())
+        .min(items.len());
+    let next = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<T>>> = Mutex::new(items.iter().map(|_| None).collect());
+    let failure: Mutex<Option<SimError>> = Mutex::new(None);
+    thread::scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(|| loop {
+                let k = next.fetch_add(1, Ordering::Relaxed);
+                if k >= items.len() {
+                    break;
+                }
+                match run(items[k]) {
+                    Ok(result) => results.lock().unwrap()[k] = Some(result),
+                    Err(e) => {
+                        next.store(items.len(), Ordering::Relaxed);
+                        failure.lock().unwrap().get_or_insert(e);
+                        break;
+                    }
+                }
+            });
+        }
+    });
+    if let Some(e) = failure.into_inner().unwrap() {
+        return Err(e);
+    }
+    Ok(results
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .flatten()
+        .collect())
+}
+
+fn quantile(sorted: &[f64], q: f64) -> f64 {
+    // Linearly interpolated quantile of an already sorted slice
+
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let position = q.clamp(0.0, 1.0) * (sorted.len() - 1) as f64;
+    let below = position.floor() as usize;
+    let above = position.ceil() as usize;
+    sorted[below] + (sorted[above] - sorted[below]) * (position - below as f64)
+}
+
+// pointwise summary of the abundance trajectories of an ensemble of replicates, with
+// mean, lower and upper indexed by species id and then by time
+pub struct AbundanceEnvelope {
+    pub t: Vec<f64>,
+    pub mean: Vec<Vec<f64>>,
+    pub lower: Vec<Vec<f64>>,
+    pub upper: Vec<Vec<f64>>,
+}
+
+fn abundances(population: &Population) -> Vec<usize> {
+    // number of living individuals of each species, indexed by id
+    let ids = population
+        .species
+        .iter()
+        .map(|x| x.id as usize + 1)
+        .max()
+        .unwrap_or(0);
+    let mut counts = vec![0; ids];
+    for individual in &population.individuals {
+        counts[individual.species.id as usize] += 1;
+    }
+    counts
+}
+
+pub fn abundance_trajectory(
+    species_list: &[&Species],
+    seed: u64,
+    times: &[f64],
+) -> Result<Vec<Vec<usize>>, SimError> {
+    // Run one replicate through the sorted times and return each species' abundance at
+    // every one of them (indexed by time, then species id), taken as the state holding
+    // at that instant rather than after the step that crosses it. A run that stops
+    // early keeps its final abundances for the remaining times
+
+    let max_t = times.last().copied().unwrap_or(0.0);
+    let mut population = Population::from_seed(species_list.to_vec(), seed)?;
+    let mut held = abundances(&population);
+    let mut trajectory = vec![];
+    population.simulate_with(max_t, |population, _| {
+        while trajectory.len() < times.len() && times[trajectory.len()] < population.t {
+            trajectory.push(held.clone());
+        }
+        held = abundances(population);
+    })?;
+    trajectory.resize(times.len(), held);
+    Ok(trajectory)
+}
+
+pub fn abundance_envelope(
+    species_list: &[&Species],
+    seeds: &[u64],
+    times: &[f64],
+    level: f64,
+) -> Result<AbundanceEnvelope, SimError> {
+    // Run one replicate per seed, in parallel, and summarise their abundance at each of
+    // the sorted times by the pointwise mean and the central `level` quantile band
+
+    let trajectories = replicates(seeds, |seed| {
+        abundance_trajectory(species_list, seed, times)
+    })?;
+
+    let ids = species_list
+        .iter()
+        .map(|x| x.id as usize + 1)
+        .max()
+        .unwrap_or(0);
+    let mut envelope = AbundanceEnvelope {
+        t: times.to_vec(),
+        mean: vec![vec![]; ids],
+        lower: vec![vec![]; ids],
+        upper: vec![vec![]; ids],
+    };
+    for id in 0..ids {
+        for k in 0..times.len() {
+            let mut values: Vec<f64> = trajectories
+                .iter()
+                .map(|trajectory| trajectory[k][id] as f64)
+                .collect();
+            values.sort_by(|a, b| a.total_cmp(b));
+            envelope.mean[id].push(values.iter().sum::<f64>() / values.len().max(1) as f64);
+            envelope.lower[id].push(quantile(&values, (1.0 - level) / 2.0));
+            envelope.upper[id].push(quantile(&values, (1.0 + level) / 2.0));
+        }
+    }
+    Ok(envelope)
+}
+
+pub struct RealizedRates {
+    pub density: Vec<f64>,
+    pub exposure: Vec<f64>,
+    pub birth_rate: Vec<f64>,
+    pub death_rate: Vec<f64>,
+}
+
+pub fn realized_rates(
+    population: &mut Population,
+    max_t: f64,
+    max_density: f64,
+    bins: usize,
+) -> Result<RealizedRates, SimError> {
+    // Simulate up to max_t, binning every individual's time at risk and every birth and
+    // death by the local (birth kernel) density it experienced just before, and return
+    // the realized per-capita rates in each density bin; with no bins every series is
+    // empty
+
+    let width = max_density / bins as f64;
+    let bin_of = |density: f64| ((density / width) as usize).min(bins.saturating_sub(1));
+    let mut exposure = vec![0.0; bins];
+    let mut births = vec![0.0; bins];
+    let mut deaths = vec![0.0; bins];
+
+    while bins > 0 && population.t < max_t {
+        let experienced: HashMap<usize, usize> = population
+            .individuals
+            .iter()
+            .map(|x| (x.uid, bin_of(x.birth_neighbor_density)))
+            .collect();
+        let t = population.t;
+        let Some(record) = population.step_within(max_t)? else {
+            // the time at risk runs on to max_t if the run stopped there
+            for bin in experienced.values() {
+                exposure[*bin] += population.t - t;
+            }
+            break;
+        };
+        for bin in experienced.values() {
+            exposure[*bin] += record.time - t;
+        }
+        let counts = match record.kind {
+            Event::Birth => &mut births,
+            Event::Death => &mut deaths,
+            _ => continue,
+        };
+        if let Some(bin) = record.individual_id.and_then(|x| experienced.get(&x)) {
+            counts[*bin] += 1.0;
+        }
+    }
+
+    let per_capita = |counts: Vec<f64>| -> Vec<f64> {
+        counts
+            .iter()
+            .zip(exposure.iter())
+            .map(|(c, e)| if *e > 0.0 { c / e } else { 0.0 })
+            .collect()
+    };
+    Ok(RealizedRates {
+        density: (0..bins).map(|k| (k as f64 + 0.5) * width).collect(),
+        birth_rate: per_capita(births),
+        death_rate: per_capita(deaths),
+        exposure,
+    })
+}
+
+pub struct Quadrats {
+    // m x m cell counts, indexed by species id
+    pub counts: Vec<Array2<usize>>,
+    // variance to mean ratio of the cell counts for each species
+    pub dispersion: Vec<f64>,
+}
+
+pub fn quadrat_counts(checkpoint: &Checkpoint, m: usize) -> Quadrats {
+    // Bin a checkpoint's individuals into an m x m grid per species; an index of
+    // dispersion above 1 indicates aggregation and below 1 regularity. With m = 0 the
+    // grids are empty and every index is 0
+
+    let species = checkpoint.counts.len();
+    let mut counts = vec![Array2::<usize>::zeros((m, m)); species];
+    if m == 0 {
+        return Quadrats {
+            counts,
+            dispersion: vec![0.0; species],
+        };
+    }
+    for (idx, id) in checkpoint.species.iter().enumerate() {
+        let (x, y) = checkpoint.coords.get(idx);
+        let row = ((y * m as f64) as usize).min(m - 1);
+        let col = ((x * m as f64) as usize).min(m - 1);
+        counts[*id as usize][[row, col]] += 1;
+    }
+
+    let cells = (m * m) as f64;
+    let dispersion = counts
+        .iter()
+        .map(|grid| {
+            let mean = grid.sum() as f64 / cells;
+            if mean == 0.0 || cells < 2.0 {
+                return 0.0;
+            }
+            let variance =
+                grid.iter().map(|c| (*c as f64 - mean).powi(2)).sum::<f64>() / (cells - 1.0);
+            variance / mean
+        })
+        .collect();
+    Quadrats { counts, dispersion }
+}
+
+pub(crate) fn torus_distance(first: (f64, f64), second: (f64, f64)) -> f64 {
+    let dx = (first.0 - second.0).abs();
+    let dy = (first.1 - second.1).abs();
+    (dx.min(1.0 - dx).powi(2) + dy.min(1.0 - dy).powi(2)).sqrt()
+}
+
+fn nearest_neighbor(
+    checkpoint: &Checkpoint,
+    idx: usize,
+    accept: impl Fn(u8) -> bool,
+) -> Option<(usize, f64)> {
+    // Closest other individual to the idx-th among the species accepted
+
+    let position = checkpoint.coords.get(idx);
+    (0..checkpoint.species.len())
+        .filter(|j| *j != idx && accept(checkpoint.species[*j]))
+        .map(|j| (j, torus_distance(position, checkpoint.coords.get(j))))
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+}
+
+pub struct NearestNeighbors {
+    pub distances: Vec<f64>,
+    pub mean: f64,
+    pub quartiles: [f64; 3],
+}
+
+pub fn nearest_neighbor_distances(checkpoint: &Checkpoint, from: u8, to: u8) -> NearestNeighbors {
+    // Distance from each individual of species `from` to its nearest neighbor of species
+    // `to` (which may be the same species), sorted ascending
+
+    let mut distances: Vec<f64> = (0..checkpoint.species.len())
+        .filter(|idx| checkpoint.species[*idx] == from)
+        .filter_map(|idx| nearest_neighbor(checkpoint, idx, |s| s == to))
+        .map(|(_, d)| d)
+        .collect();
+    distances.sort_by(|a, b| a.total_cmp(b));
+
+    NearestNeighbors {
+        mean: distances.iter().sum::<f64>() / distances.len().max(1) as f64,
+        quartiles: [0.25, 0.5, 0.75].map(|q| quantile(&distances, q)),
+        distances,
+    }
+}
+
+pub fn segregation(checkpoint: &Checkpoint, first: u8, second: u8) -> f64 {
+    // Pielou's coefficient of segregation between two species, from the species of each
+    // individual's nearest neighbor among both: 1 when neighbors are always conspecific,
+    // 0 under random labelling, and negative when the species alternate
+
+    let pooled = |s: u8| s == first || s == second;
+    let mut table = [[0.0; 2]; 2];
+    for idx in (0..checkpoint.species.len()).filter(|idx| pooled(checkpoint.species[*idx])) {
+        if let Some((neighbor, _)) = nearest_neighbor(checkpoint, idx, pooled) {
+            let row = (checkpoint.species[idx] == second) as usize;
+            let col = (checkpoint.species[neighbor] == second) as usize;
+            table[row][col] += 1.0;
+        }
+    }
+
+    let rows = [table[0][0] + table[0][1], table[1][0] + table[1][1]];
+    let cols = [table[0][0] + table[1][0], table[0][1] + table[1][1]];
+    let mixed = rows[0] * cols[1] + rows[1] * cols[0];
+    if mixed == 0.0 {
+        return 0.0;
+    }
+    let expected = mixed / (rows[0] + rows[1]);
+    1.0 - (table[0][1] + table[1][0]) / expected
+}
+
+pub struct Clusters {
+    // cluster of each individual in the checkpoint, None for noise or other species
+    pub labels: Vec<Option<usize>>,
+    pub sizes: Vec<usize>,
+    pub centroids: Vec<(f64, f64)>,
+}
+
+pub fn clusters(
+    checkpoint: &Checkpoint,
+    eps: f64,
+    min_points: usize,
+    species: Option<u8>,
+) -> Clusters {
+    // DBSCAN over the individuals (optionally of one species) using torus distances: a
+    // cluster grows from every core point with at least min_points neighbors (itself
+    // included) within eps, and absorbs the border points it reaches
+
+    let members: Vec<usize> = (0..checkpoint.species.len())
+        .filter(|idx| species.is_none_or(|s| s == checkpoint.species[*idx]))
+        .collect();
+    let neighbors = |idx: usize| -> Vec<usize> {
+        let position = checkpoint.coords.get(idx);
+        members
+            .iter()
+            .copied()
+            .filter(|j| torus_distance(position, checkpoint.coords.get(*j)) <= eps)
+            .collect()
+    };
+
+    let mut labels = vec![None; checkpoint.species.len()];
+    let mut visited = vec![false; checkpoint.species.len()];
+    let mut sizes = vec![];
+    for &seed in &members {
+        if visited[seed] {
+            continue;
+        }
+        visited[seed] = true;
+        let mut frontier = neighbors(seed);
+        if frontier.len() < min_points {
+            continue;
+        }
+
+        let cluster = sizes.len();
+        labels[seed] = Some(cluster);
+        let mut size = 1;
+        while let Some(idx) = frontier.pop() {
+            if labels[idx].is_none() {
+                labels[idx] = Some(cluster);
+                size += 1;
+            }
+            if !visited[idx] {
+                visited[idx] = true;
+                let reach = neighbors(idx);
+                if reach.len() >= min_points {
+                    frontier.extend(reach);
+                }
+            }
+        }
+        sizes.push(size);
+    }
+
+    // centroids as circular means, so clusters straddling an edge are not split
+    let mut sums = vec![[0.0; 4]; sizes.len()];
+    for (idx, label) in labels.iter().enumerate() {
+        if let Some(cluster) = label {
+            let (x, y) = checkpoint.coords.get(idx);
+            let (x, y) = (2.0 * PI * x, 2.0 * PI * y);
+            sums[*cluster][0] += x.sin();
+            sums[*cluster][1] += x.cos();
+            sums[*cluster][2] += y.sin();
+            sums[*cluster][3] += y.cos();
+        }
+    }
+    let centroids = sums
+        .iter()
+        .map(|[xs, xc, ys, yc]| {
+            (
+                (xs.atan2(*xc) / (2.0 * PI)).rem_euclid(1.0),
+                (ys.atan2(*yc) / (2.0 * PI)).rem_euclid(1.0),
+            )
+        })
+        .collect();
+
+    Clusters {
+        labels,
+        sizes,
+        centroids,
+    }
+}
+
+pub fn extinction_times(population: &Population) -> Vec<Option<f64>> {
+    // Time at which each species (indexed by id) went extinct, taken as the last death
+    // among its lineages, or None while it persists
+
+    let ids = population
+        .species
+        .iter()
+        .map(|x| x.id as usize + 1)
+        .max()
+        .unwrap_or(0);
+    let mut alive = vec![false; ids];
+    for individual in &population.individuals {
+        alive[individual.species.id as usize] = true;
+    }
+    let mut extinctions = vec![None; ids];
+    for lineage in &population.genealogy.lineages {
+        let id = lineage.species as usize;
+        if let (false, Some(death_time)) = (alive[id], lineage.death_time) {
+            extinctions[id] = Some(death_time.max(extinctions[id].unwrap_or(0.0)));
+        }
+    }
+    extinctions
+}
+
+pub fn replicate_extinctions(
+    species_list: &[&Species],
+    seeds: &[u64],
+    max_t: f64,
+) -> Result<Vec<Vec<Option<f64>>>, SimError> {
+    // Run one replicate per seed up to max_t and return each replicate's extinction
+    // times, censored (None) for species still present at max_t
+
+    replicates(seeds, |seed| {
+        let mut population = Population::from_seed(species_list.to_vec(), seed)?;
+        population.simulate(max_t)?;
+        Ok(extinction_times(&population))
+    })
+}
+
+pub struct Survival {
+    // step function: survival[k] holds from t[k] until t[k + 1]
+    pub t: Vec<f64>,
+    pub survival: Vec<f64>,
+    pub at_risk: Vec<usize>,
+}
+
+pub fn survival_curve(times: &[Option<f64>]) -> Survival {
+    // Kaplan-Meier estimate of the probability of persisting beyond t, from extinction
+    // times with None marking replicates censored at the end of the run
+
+    let mut events: Vec<f64> = times.iter().flatten().copied().collect();
+    events.sort_by(|a, b| a.total_cmp(b));
+
+    let mut curve = Survival {
+        t: vec![0.0],
+        survival: vec![1.0],
+        at_risk: vec![times.len()],
+    };
+    let mut at_risk = times.len();
+    let mut survival = 1.0;
+    let mut k = 0;
+    while k < events.len() {
+        let t = events[k];
+        let deaths = events[k..].iter().take_while(|x| **x == t).count();
+        survival *= 1.0 - deaths as f64 / at_risk as f64;
+        curve.t.push(t);
+        curve.survival.push(survival);
+        curve.at_risk.push(at_risk);
+        at_risk -= deaths;
+        k += deaths;
+    }
+    curve
+}
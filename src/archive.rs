@@ -0,0 +1,342 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use ndarray::Array2;
+
+use crate::heatmap::Kde;
+use crate::history::{
+    Change, Checkpoint, Coordinates, HarvestRecord, History, LogEntry, Precision,
+};
+
+// Little-endian binary archive of a History: the magic bytes and a format version,
+// followed by the fields in declaration order. Sequences are prefixed by their length
+// and optional values by a 0/1 flag. Bump VERSION whenever the layout changes.
+const MAGIC: &[u8; 4] = b"PDSH";
+pub(crate) const VERSION: u16 = 1;
+
+pub(crate) fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+pub(crate) struct Encoder {
+    pub(crate) bytes: Vec<u8>,
+}
+
+impl Encoder {
+    pub(crate) fn u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    pub(crate) fn u64(&mut self, value: usize) {
+        self.bytes.extend((value as u64).to_le_bytes());
+    }
+
+    pub(crate) fn f32(&mut self, value: f32) {
+        self.bytes.extend(value.to_le_bytes());
+    }
+
+    pub(crate) fn f64(&mut self, value: f64) {
+        self.bytes.extend(value.to_le_bytes());
+    }
+
+    pub(crate) fn flag<T>(&mut self, value: &Option<T>) {
+        self.u8(value.is_some() as u8);
+    }
+
+    fn checkpoint(&mut self, checkpoint: &Checkpoint) {
+        let n = checkpoint.uids.len();
+        self.f64(checkpoint.t);
+        self.u64(n);
+        checkpoint.uids.iter().for_each(|x| self.u64(*x));
+        self.bytes.extend(&checkpoint.species);
+        match &checkpoint.coords {
+            Coordinates::Single(coords) => {
+                self.u8(0);
+                coords.iter().flatten().for_each(|x| self.f32(*x));
+            }
+            Coordinates::Double(coords) => {
+                self.u8(1);
+                coords.iter().flatten().for_each(|x| self.f64(*x));
+            }
+        }
+        checkpoint.traits.iter().for_each(|x| self.f64(*x));
+        for genome in &checkpoint.genomes {
+            self.u64(genome.len());
+            self.bytes.extend(genome);
+        }
+        self.u64(checkpoint.counts.len());
+        checkpoint.counts.iter().for_each(|x| self.u64(*x));
+        for name in &checkpoint.names {
+            self.u64(name.len());
+            self.bytes.extend(name.as_bytes());
+        }
+        checkpoint.colors.iter().for_each(|x| self.bytes.extend(x));
+        self.flag(&checkpoint.heatmap);
+        if let Some(grid) = &checkpoint.heatmap {
+            self.u64(grid.nrows());
+            self.u64(grid.ncols());
+            grid.iter().for_each(|x| self.f64(*x));
+        }
+        self.u64(checkpoint.logged);
+    }
+
+    fn log_entry(&mut self, entry: &LogEntry) {
+        self.f64(entry.t);
+        self.u64(entry.uid);
+        match entry.change {
+            Change::Added { species, x, y } => {
+                self.u8(0);
+                self.u8(species);
+                self.f64(x);
+                self.f64(y);
+            }
+            Change::Removed => self.u8(1),
+            Change::Moved { x, y } => {
+                self.u8(2);
+                self.f64(x);
+                self.f64(y);
+            }
+        }
+    }
+
+    pub(crate) fn history(&mut self, history: &History) {
+        self.u8(match history.precision {
+            Precision::Single => 0,
+            Precision::Double => 1,
+        });
+        self.flag(&history.heatmap);
+        if let Some(kde) = history.heatmap {
+            self.u64(kde.resolution);
+            self.f64(kde.bandwidth);
+            self.flag(&kde.species);
+            self.u8(kde.species.unwrap_or(0));
+        }
+        self.u64(history.checkpoints.len());
+        history.checkpoints.iter().for_each(|x| self.checkpoint(x));
+        self.u64(history.harvests.len());
+        for harvest in &history.harvests {
+            self.f64(harvest.t);
+            self.u8(harvest.species);
+            self.u64(harvest.removed);
+        }
+        self.flag(&history.events);
+        if let Some(events) = &history.events {
+            self.u64(events.len());
+            events.iter().for_each(|x| self.log_entry(x));
+        }
+    }
+}
+
+pub(crate) struct Decoder<'b> {
+    pub(crate) bytes: &'b [u8],
+}
+
+impl<'b> Decoder<'b> {
+    pub(crate) fn take(&mut self, count: usize) -> io::Result<&'b [u8]> {
+        if count > self.bytes.len() {
+            return Err(invalid("archive is truncated"));
+        }
+        let (head, rest) = self.bytes.split_at(count);
+        self.bytes = rest;
+        Ok(head)
+    }
+
+    pub(crate) fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub(crate) fn u64(&mut self) -> io::Result<usize> {
+        let value = u64::from_le_bytes(self.take(8)?.try_into().unwrap());
+        usize::try_from(value).map_err(|_| invalid("archive length out of range"))
+    }
+
+    pub(crate) fn f32(&mut self) -> io::Result<f32> {
+        Ok(f32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub(crate) fn f64(&mut self) -> io::Result<f64> {
+        Ok(f64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    pub(crate) fn flag(&mut self) -> io::Result<bool> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            tag => Err(invalid(format!("invalid flag {tag} in archive"))),
+        }
+    }
+
+    pub(crate) fn many<T>(
+        &mut self,
+        count: usize,
+        mut read: impl FnMut(&mut Self) -> io::Result<T>,
+    ) -> io::Result<Vec<T>> {
+        // cap the preallocation so a corrupt length fails on reading, not allocating
+        let mut values = Vec::with_capacity(count.min(self.bytes.len()));
+        for _ in 0..count {
+            values.push(read(self)?);
+        }
+        Ok(values)
+    }
+
+    fn checkpoint(&mut self) -> io::Result<Checkpoint> {
+        let t = self.f64()?;
+        let n = self.u64()?;
+        let uids = self.many(n, Self::u64)?;
+        let species = self.take(n)?.to_vec();
+        let coords = match self.u8()? {
+            0 => Coordinates::Single(self.many(n, |d| Ok([d.f32()?, d.f32()?]))?),
+            1 => Coordinates::Double(self.many(n, |d| Ok([d.f64()?, d.f64()?]))?),
+            tag => {
+                return Err(invalid(format!(
+                    "invalid precision {tag} in history archive"
+                )))
+            }
+        };
+        let traits = self.many(n, Self::f64)?;
+        let genomes = self.many(n, |d| {
+            let len = d.u64()?;
+            Ok(d.take(len)?.to_vec())
+        })?;
+        let len = self.u64()?;
+        let counts = self.many(len, Self::u64)?;
+        let names = self.many(len, |d| {
+            let len = d.u64()?;
+            String::from_utf8(d.take(len)?.to_vec()).map_err(|e| invalid(e.to_string()))
+        })?;
+        let colors = self.many(len, |d| Ok([d.u8()?, d.u8()?, d.u8()?]))?;
+        let heatmap = if self.flag()? {
+            let (rows, cols) = (self.u64()?, self.u64()?);
+            let values = self.many(rows.saturating_mul(cols), Self::f64)?;
+            let grid =
+                Array2::from_shape_vec((rows, cols), values).map_err(|e| invalid(e.to_string()))?;
+            Some(grid)
+        } else {
+            None
+        };
+        let logged = self.u64()?;
+        Ok(Checkpoint {
+            t,
+            uids,
+            species,
+            coords,
+            traits,
+            genomes,
+            counts,
+            names,
+            colors,
+            heatmap,
+            logged,
+        })
+    }
+
+    fn log_entry(&mut self) -> io::Result<LogEntry> {
+        let t = self.f64()?;
+        let uid = self.u64()?;
+        let change = match self.u8()? {
+            0 => Change::Added {
+                species: self.u8()?,
+                x: self.f64()?,
+                y: self.f64()?,
+            },
+            1 => Change::Removed,
+            2 => Change::Moved {
+                x: self.f64()?,
+                y: self.f64()?,
+            },
+            tag => return Err(invalid(format!("invalid change {tag} in history archive"))),
+        };
+        Ok(LogEntry { t, uid, change })
+    }
+
+    pub(crate) fn history(&mut self) -> io::Result<History> {
+        let precision = match self.u8()? {
+            0 => Precision::Single,
+            1 => Precision::Double,
+            tag => {
+                return Err(invalid(format!(
+                    "invalid precision {tag} in history archive"
+                )))
+            }
+        };
+        let heatmap = if self.flag()? {
+            let resolution = self.u64()?;
+            let bandwidth = self.f64()?;
+            let has_species = self.flag()?;
+            let species = self.u8()?;
+            Some(Kde {
+                resolution,
+                bandwidth,
+                species: has_species.then_some(species),
+            })
+        } else {
+            None
+        };
+        let count = self.u64()?;
+        let checkpoints = self.many(count, Self::checkpoint)?;
+        let count = self.u64()?;
+        let harvests = self.many(count, |d| {
+            Ok(HarvestRecord {
+                t: d.f64()?,
+                species: d.u8()?,
+                removed: d.u64()?,
+            })
+        })?;
+        let events = if self.flag()? {
+            let count = self.u64()?;
+            Some(self.many(count, Self::log_entry)?)
+        } else {
+            None
+        };
+        let entries = events.as_ref().map_or(0, Vec::len);
+        if checkpoints.iter().any(|x| x.logged > entries) {
+            return Err(invalid("checkpoint past the end of the event log"));
+        }
+        Ok(History {
+            precision,
+            checkpoints,
+            harvests,
+            events,
+            heatmap,
+        })
+    }
+}
+
+impl History {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut encoder = Encoder { bytes: vec![] };
+        encoder.bytes.extend(MAGIC);
+        encoder.bytes.extend(VERSION.to_le_bytes());
+        encoder.history(self);
+        encoder.bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<Self> {
+        // Decode an archive written by to_bytes, rejecting other formats and versions
+
+        let mut decoder = Decoder { bytes };
+        if decoder.take(4).ok() != Some(MAGIC.as_slice()) {
+            return Err(invalid("not a history archive"));
+        }
+        let version = u16::from_le_bytes(decoder.take(2)?.try_into().unwrap());
+        if version != VERSION {
+            return Err(invalid(format!(
+                "unsupported history archive version {version} (expected {VERSION})"
+            )));
+        }
+        let history = decoder.history()?;
+        if !decoder.bytes.is_empty() {
+            return Err(invalid("trailing bytes after history archive"));
+        }
+        Ok(history)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, self.to_bytes())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        History::from_bytes(&fs::read(path)?)
+    }
+}
@@ -0,0 +1,121 @@
+use std::fmt;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use rand::Rng;
+
+use crate::ndjson::checkpoint_json;
+use crate::streams::{Stream, Streams};
+use crate::{Population, SimError, Species};
+
+// Timings of the hot paths at several population sizes, so that a change to the
+// neighbour or sampling code can be measured against the previous build. Run from a
+// release build, as cargo bench --bench hot_paths does; each figure is a mean over its
+// iterations.
+pub struct Timing {
+    pub name: &'static str,
+    pub size: usize,
+    pub iterations: usize,
+    pub mean: Duration,
+}
+
+impl fmt::Display for Timing {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:<24} n = {:>6} {:>12.3?} (mean of {})",
+            self.name, self.size, self.mean, self.iterations
+        )
+    }
+}
+
+pub fn bench_species() -> Species {
+    // a single species with every neighbour kernel switched on, so each step exercises
+    // the birth, death and movement densities
+    Species {
+        B0: 0.4,
+        D0: 0.2,
+        D1: 0.001,
+        Wbrmax: 0.1,
+        Wbsd: 0.05,
+        Wdrmax: 0.1,
+        Wdsd: 0.05,
+        Mbsd: 0.05,
+        Mintegral: 0.5,
+        Mdsd: 0.02,
+        Mrmax: 0.1,
+        Msd: 0.05,
+        ..Default::default()
+    }
+}
+
+pub fn synthetic_population(species: &Species, size: usize, seed: u64) -> Population<'_> {
+    // size individuals of one species placed uniformly at random, ignoring its C1 and
+    // initial pattern
+
+    let mut streams = Streams::new(seed);
+    let rng = streams.get(Stream::Initial);
+    let placements = (0..size)
+        .map(|_| (species, (rng.gen::<f64>(), rng.gen::<f64>())))
+        .collect();
+    Population::from_placements(vec![species], placements, seed, streams)
+}
+
+fn time<T>(iterations: usize, mut f: impl FnMut() -> T) -> Duration {
+    let start = Instant::now();
+    for _ in 0..iterations {
+        black_box(f());
+    }
+    start.elapsed() / iterations.max(1) as u32
+}
+
+pub fn run_benchmarks(sizes: &[usize], steps: usize) -> Result<Vec<Timing>, SimError> {
+    // For each size: building a population (its grid and initial densities), a step
+    // of the exact simulation, and serialising the resulting history as an archive
+    // and its last checkpoint as JSON
+
+    let species = bench_species();
+    species.validate()?;
+    let mut timings = vec![];
+    for &size in sizes {
+        let setup_iterations = (200_000 / size.pow(2).max(1)).clamp(1, 100);
+        timings.push(Timing {
+            name: "initial densities",
+            size,
+            iterations: setup_iterations,
+            mean: time(setup_iterations, || synthetic_population(&species, size, 0)),
+        });
+
+        let mut population = synthetic_population(&species, size, 0);
+        let mut taken = 0;
+        let start = Instant::now();
+        while taken < steps && population.step()?.is_some() {
+            population.record_checkpoint();
+            taken += 1;
+        }
+        // the step figure includes recording a checkpoint, as simulate does
+        timings.push(Timing {
+            name: "step",
+            size,
+            iterations: taken,
+            mean: start.elapsed() / taken.max(1) as u32,
+        });
+
+        let history = &population.history;
+        timings.push(Timing {
+            name: "history archive",
+            size,
+            iterations: 10,
+            mean: time(10, || history.to_bytes()),
+        });
+        if let Some(checkpoint) = history.checkpoints.last() {
+            timings.push(Timing {
+                name: "checkpoint json",
+                size,
+                iterations: 100,
+                mean: time(100, || checkpoint_json(checkpoint)),
+            });
+        }
+    }
+    Ok(timings)
+}
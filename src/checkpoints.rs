@@ -0,0 +1,167 @@
+use std::time::{Duration, Instant};
+
+use crate::history::Checkpoint;
+use crate::{Population, SimError, StopReason};
+
+// Pull-based delivery of checkpoints: each call to next runs the simulation only until
+// the next checkpoint is recorded, so a consumer that stops pulling stops the run. This
+// stands in for an async Stream, for which no executor is available to this crate; a
+// server or GUI can drive it from a worker thread and forward over a bounded channel.
+pub struct Checkpoints<'p, 'a> {
+    population: &'p mut Population<'a>,
+    max_t: f64,
+    // index in the history of the first checkpoint not yet handed out
+    next: usize,
+    next_check: f64,
+    reason: Option<StopReason>,
+    failed: bool,
+}
+
+impl<'a> Population<'a> {
+    pub fn checkpoints(&mut self, max_t: f64) -> Checkpoints<'_, 'a> {
+        // Simulate up to max_t as simulate would, yielding each checkpoint as it is
+        // recorded. Handed-out checkpoints are dropped from the history apart from the
+        // latest, unless a stationarity detector needs them, so memory stays flat
+
+        if self.history.checkpoints.is_empty() {
+            self.record_checkpoint();
+        }
+        let next_check = self.t;
+        Checkpoints {
+            population: self,
+            max_t,
+            next: 0,
+            next_check,
+            reason: None,
+            failed: false,
+        }
+    }
+}
+
+// when a batch of checkpoints is handed over: once interval of wall-clock time has
+// passed since it was requested, or once it holds max_rows individuals in total,
+// whichever comes first, and never empty
+#[derive(Clone, Copy)]
+pub struct FlushPolicy {
+    pub interval: Duration,
+    pub max_rows: usize,
+}
+
+pub struct Batches<'p, 'a> {
+    checkpoints: Checkpoints<'p, 'a>,
+    policy: FlushPolicy,
+    // a failure held back until the checkpoints before it have been handed over
+    error: Option<SimError>,
+}
+
+impl<'p, 'a> Checkpoints<'p, 'a> {
+    pub fn reason(&self) -> Option<StopReason> {
+        // why the run stopped, once it has
+        self.reason
+    }
+
+    pub fn batched(self, policy: FlushPolicy) -> Batches<'p, 'a> {
+        Batches {
+            checkpoints: self,
+            policy,
+            error: None,
+        }
+    }
+
+    fn pop(&mut self) -> Option<Checkpoint> {
+        // hand out the next recorded checkpoint, dropping those before it
+        let population = &mut *self.population;
+        let checkpoint = population.history.checkpoints.get(self.next)?.clone();
+        self.next += 1;
+        if population.stationarity.is_none() {
+            let drained = self.next.min(population.history.checkpoints.len() - 1);
+            population.history.checkpoints.drain(..drained);
+            self.next -= drained;
+        }
+        Some(checkpoint)
+    }
+
+    fn advance(&mut self) -> Result<bool, SimError> {
+        // take one step, or false once the run has stopped; a run that stops first fills
+        // its remaining output times up to max_t, which counts as a step
+
+        if self.reason.is_some() || self.failed {
+            return Ok(false);
+        }
+        let population = &mut *self.population;
+        if population.t >= self.max_t {
+            self.reason = Some(StopReason::MaxTime);
+        } else if let Some(reason) = population.early_stop(&mut self.next_check) {
+            self.reason = Some(reason);
+        } else {
+            match population.progress(self.max_t) {
+                Ok(true) => {
+                    population.record_checkpoint();
+                    population.check_watchers();
+                    return Ok(true);
+                }
+                Ok(false) => self.reason = Some(StopReason::Extinct),
+                Err(e) => {
+                    self.failed = true;
+                    return Err(e);
+                }
+            }
+        }
+        population.fill_output_times(self.max_t);
+        Ok(true)
+    }
+}
+
+impl Iterator for Checkpoints<'_, '_> {
+    type Item = Result<Checkpoint, SimError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(checkpoint) = self.pop() {
+                return Some(Ok(checkpoint));
+            }
+            match self.advance() {
+                Ok(true) => (),
+                Ok(false) => return None,
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+impl Iterator for Batches<'_, '_> {
+    type Item = Result<Vec<Checkpoint>, SimError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // the clock is checked between steps, so a batch is not held back waiting for a
+        // slow checkpoint once its interval is up
+
+        if let Some(e) = self.error.take() {
+            return Some(Err(e));
+        }
+        let start = Instant::now();
+        let mut batch = vec![];
+        let mut rows = 0;
+        loop {
+            while let Some(checkpoint) = self.checkpoints.pop() {
+                rows += checkpoint.uids.len();
+                batch.push(checkpoint);
+                if rows >= self.policy.max_rows {
+                    return Some(Ok(batch));
+                }
+            }
+            if !batch.is_empty() && start.elapsed() >= self.policy.interval {
+                return Some(Ok(batch));
+            }
+            match self.checkpoints.advance() {
+                Ok(true) => (),
+                Ok(false) => return (!batch.is_empty()).then_some(Ok(batch)),
+                Err(e) if batch.is_empty() => return Some(Err(e)),
+                Err(e) => {
+                    self.error = Some(e);
+                    return Some(Ok(batch));
+                }
+            }
+        }
+    }
+}
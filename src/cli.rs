@@ -0,0 +1,672 @@
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use crate::analysis::{extinction_times, pcf_envelope};
+use crate::config::{apply_species_toml, merge_toml, parse_kde, read_toml, SimulationConfig};
+use crate::heatmap::Kde;
+use crate::history::csv_field;
+use crate::loader::{f64_field, load_species};
+use crate::ndjson::event_json;
+use crate::replay::verify_config_replay;
+use crate::sweep::{sweep_with, SweepAxis};
+use crate::watch::Condition;
+use crate::{Population, Species, StopReason};
+
+pub const USAGE: &str = "usage: simulate <species file> [--config <toml>] [--until <t>] \
+[--seed <n>] [--out <dir>] [--quiet] [--replicates <n>] [--seed-start <n>] \
+[--sweep [<species id>.]<field>=<v1>,<v2>,...]... [--only <run>] [--resume <state>] \
+[--save-state <state>] [--watch <condition>]... \
+[--heatmap <resolution>,<bandwidth>[,<species id>]]
+       simulate profile <species file> [--config <toml>] [--until <t>] [--seed <n>] \
+[--calibrate <t>]
+       simulate replay <species file> [--config <toml>] [--until <t>] [--seed <n>]
+       simulate analyze <species file> [--config <toml>] [--until <t>] [--replicates <n>] \
+[--seed-start <n>] [--max-r <r>] [--bins <n>] [--level <q>]";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Command {
+    // simulate and write the outputs
+    Run,
+    // time each phase of a short calibration run and project the whole run
+    Profile,
+    // run the scenario twice in lockstep, failing unless the two runs agree
+    Replay,
+    // pair correlation envelope across replicates at the final time, as CSV on stdout
+    Analyze,
+}
+
+// one swept species parameter, set on the species with the given id or on every species
+pub struct Sweep {
+    pub species: Option<u8>,
+    pub field: String,
+    pub values: Vec<f64>,
+}
+
+pub struct Options {
+    pub command: Command,
+    // species parameters as csv, toml, or json
+    pub species: PathBuf,
+    // run settings and species overrides, read with config::read_toml
+    pub config: Option<PathBuf>,
+    // final time and seed, overriding the config file
+    pub until: Option<f64>,
+    pub seed: Option<u64>,
+    // directory for history.csv, events.ndjson, summary.csv and any heatmap.csv, with
+    // the config as merged from the files it extends in config.toml; without one the
+    // history is written to stdout
+    pub out: Option<PathBuf>,
+    pub quiet: bool,
+    // batch runs: replicates seeded seed_start, seed_start + 1, ... for every
+    // combination of swept values, optionally restricted to a single run index
+    pub replicates: usize,
+    pub seed_start: Option<u64>,
+    pub sweeps: Vec<Sweep>,
+    pub only: Option<usize>,
+    // continue from a state saved by an earlier run of the same species, and save the
+    // final state; single runs only
+    pub resume: Option<PathBuf>,
+    pub save_state: Option<PathBuf>,
+    // conditions reported on stderr each time they start to hold
+    pub watches: Vec<Condition>,
+    // density estimate of each checkpoint, written to heatmap.csv; overrides the config
+    pub heatmap: Option<Kde>,
+    // simulated time the profile is calibrated over
+    pub calibration: Option<f64>,
+    // annuli of the pair correlation function and the quantile band of its envelope
+    pub max_r: Option<f64>,
+    pub bins: Option<usize>,
+    pub level: Option<f64>,
+}
+
+impl Options {
+    fn is_batch(&self) -> bool {
+        self.replicates > 1 || !self.sweeps.is_empty() || self.only.is_some()
+    }
+}
+
+fn parse_sweep(spec: &str) -> Result<Sweep, String> {
+    let bad = || format!("--sweep expects [<species id>.]<field>=<v1>,<v2>,..., got {spec}");
+    let (target, values) = spec.split_once('=').ok_or_else(bad)?;
+    let (species, field) = match target.split_once('.') {
+        Some((id, field)) => (Some(id.parse().map_err(|_| bad())?), field),
+        None => (None, target),
+    };
+    if f64_field(&mut Species::default(), field).is_none() {
+        return Err(format!("--sweep: {field} is not a numeric species field"));
+    }
+    let values = values
+        .split(',')
+        .map(|x| x.trim().parse::<f64>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| bad())?;
+    Ok(Sweep {
+        species,
+        field: field.to_string(),
+        values,
+    })
+}
+
+fn parse_watch(spec: &str) -> Result<Condition, String> {
+    // below:<species id>:<count>, above:<species id>:<count>, or
+    // density:<x_min>,<y_min>,<x_max>,<y_max>:<density>[:<species id>]
+
+    let bad = || {
+        format!(
+            "--watch expects below:<species>:<count>, above:<species>:<count> or \
+             density:<x_min>,<y_min>,<x_max>,<y_max>:<density>[:<species>], got {spec}"
+        )
+    };
+    let parts: Vec<&str> = spec.split(':').collect();
+    let condition = match parts[..] {
+        ["below", species, count] => Condition::CountBelow {
+            species: species.parse().map_err(|_| bad())?,
+            count: count.parse().map_err(|_| bad())?,
+        },
+        ["above", species, count] => Condition::CountAbove {
+            species: species.parse().map_err(|_| bad())?,
+            count: count.parse().map_err(|_| bad())?,
+        },
+        ["density", region, density, ref species @ ..] if species.len() <= 1 => {
+            let region: Vec<f64> = region
+                .split(',')
+                .map(|x| x.trim().parse())
+                .collect::<Result<_, _>>()
+                .map_err(|_| bad())?;
+            Condition::DensityAbove {
+                region: region.try_into().map_err(|_| bad())?,
+                species: match species {
+                    [id] => Some(id.parse().map_err(|_| bad())?),
+                    _ => None,
+                },
+                density: density.parse().map_err(|_| bad())?,
+            }
+        }
+        _ => return Err(bad()),
+    };
+    Ok(condition)
+}
+
+pub fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut species = None;
+    let (command, args) = match args.first().map(String::as_str) {
+        Some("profile") => (Command::Profile, &args[1..]),
+        Some("replay") => (Command::Replay, &args[1..]),
+        Some("analyze") => (Command::Analyze, &args[1..]),
+        _ => (Command::Run, args),
+    };
+    let mut options = Options {
+        command,
+        species: PathBuf::new(),
+        config: None,
+        until: None,
+        seed: None,
+        out: None,
+        quiet: false,
+        replicates: 1,
+        seed_start: None,
+        sweeps: vec![],
+        only: None,
+        resume: None,
+        save_state: None,
+        watches: vec![],
+        heatmap: None,
+        calibration: None,
+        max_r: None,
+        bins: None,
+        level: None,
+    };
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| format!("{arg} needs a value"))
+                .cloned()
+        };
+        let integer = |value: String| {
+            value
+                .parse::<u64>()
+                .map_err(|_| format!("{arg} must be a non-negative integer, got {value}"))
+        };
+        let number = |value: String| {
+            value
+                .parse::<f64>()
+                .map_err(|_| format!("{arg} must be a number, got {value}"))
+        };
+        match arg.as_str() {
+            "--config" => options.config = Some(value()?.into()),
+            "--until" => options.until = Some(number(value()?)?),
+            "--calibrate" => options.calibration = Some(number(value()?)?),
+            "--max-r" => options.max_r = Some(number(value()?)?),
+            "--bins" => options.bins = Some(integer(value()?)? as usize),
+            "--level" => options.level = Some(number(value()?)?),
+            "--seed" => options.seed = Some(integer(value()?)?),
+            "--out" => options.out = Some(value()?.into()),
+            "--quiet" => options.quiet = true,
+            "--replicates" => options.replicates = integer(value()?)? as usize,
+            "--seed-start" => options.seed_start = Some(integer(value()?)?),
+            "--sweep" => options.sweeps.push(parse_sweep(&value()?)?),
+            "--only" => options.only = Some(integer(value()?)? as usize),
+            "--resume" => options.resume = Some(value()?.into()),
+            "--save-state" => options.save_state = Some(value()?.into()),
+            "--watch" => options.watches.push(parse_watch(&value()?)?),
+            "--heatmap" => {
+                let value = value()?;
+                let kde = value
+                    .split(',')
+                    .map(|x| x.trim().parse().ok())
+                    .collect::<Option<Vec<f64>>>()
+                    .and_then(|values| parse_kde(&values));
+                options.heatmap = Some(kde.ok_or_else(|| {
+                    format!(
+                        "--heatmap expects <resolution>,<bandwidth>[,<species id>], got {value}"
+                    )
+                })?);
+            }
+            flag if flag.starts_with("--") => return Err(format!("unknown option {flag}")),
+            path if species.is_none() => species = Some(PathBuf::from(path)),
+            extra => return Err(format!("unexpected argument {extra}")),
+        }
+    }
+    options.species = species.ok_or_else(|| USAGE.to_string())?;
+    if options.replicates == 0 {
+        return Err("--replicates must be at least 1".to_string());
+    }
+    let command = options.command;
+    if command == Command::Run && options.is_batch() && options.out.is_none() {
+        return Err("batch runs need an output directory (--out)".to_string());
+    }
+    if options.is_batch() && (options.resume.is_some() || options.save_state.is_some()) {
+        return Err("--resume and --save-state apply to single runs only".to_string());
+    }
+    if command != Command::Run
+        && (!options.sweeps.is_empty()
+            || options.only.is_some()
+            || options.out.is_some()
+            || options.resume.is_some()
+            || options.save_state.is_some())
+    {
+        return Err("sweep, output and state options apply to runs only".to_string());
+    }
+    if command != Command::Run && command != Command::Analyze && options.replicates > 1 {
+        return Err("--replicates applies to runs and analyze only".to_string());
+    }
+    if command != Command::Profile && options.calibration.is_some() {
+        return Err("--calibrate applies to profile only".to_string());
+    }
+    if command != Command::Analyze
+        && (options.max_r.is_some() || options.bins.is_some() || options.level.is_some())
+    {
+        return Err("--max-r, --bins and --level apply to analyze only".to_string());
+    }
+    if options.max_r.is_some_and(|r| !(r > 0.0 && r <= 0.5)) {
+        return Err("--max-r must lie in (0, 0.5]".to_string());
+    }
+    if options.bins == Some(0) {
+        return Err("--bins must be at least 1".to_string());
+    }
+    if options.level.is_some_and(|q| !(0.0..=1.0).contains(&q)) {
+        return Err("--level must lie in [0, 1]".to_string());
+    }
+    Ok(options)
+}
+
+struct ProgressBar {
+    start: f64,
+    end: f64,
+    // percentage last drawn
+    drawn: Option<usize>,
+}
+
+impl ProgressBar {
+    const WIDTH: usize = 40;
+
+    fn update(&mut self, t: f64, size: usize) {
+        // redraw on stderr only when the whole percentage changes
+        let fraction = ((t - self.start) / (self.end - self.start)).clamp(0.0, 1.0);
+        let percent = (fraction * 100.0) as usize;
+        if self.drawn == Some(percent) {
+            return;
+        }
+        self.drawn = Some(percent);
+        let filled = (fraction * ProgressBar::WIDTH as f64) as usize;
+        eprint!(
+            "\r[{}{}] {percent:>3}% t = {t:.3} n = {size}   ",
+            "#".repeat(filled),
+            "-".repeat(ProgressBar::WIDTH - filled)
+        );
+    }
+
+    fn finish(&self) {
+        if self.drawn.is_some() {
+            eprintln!();
+        }
+    }
+}
+
+// how a run ended, with final abundance and extinction time for each of its species
+struct Outcome {
+    seed: u64,
+    reason: StopReason,
+    t: f64,
+    steps: usize,
+    species: Vec<(u8, String, usize, Option<f64>)>,
+}
+
+fn outcome(population: &Population, reason: StopReason, steps: usize) -> Outcome {
+    let extinctions = extinction_times(population);
+    let species = population
+        .species
+        .iter()
+        .map(|species| {
+            let abundance = population
+                .individuals
+                .iter()
+                .filter(|x| x.species.id == species.id)
+                .count();
+            (
+                species.id,
+                species.label(),
+                abundance,
+                extinctions[species.id as usize],
+            )
+        })
+        .collect();
+    Outcome {
+        seed: population.seed,
+        reason,
+        t: population.t,
+        steps,
+        species,
+    }
+}
+
+fn write_summary(population: &Population, outcome: &Outcome, path: &Path) -> io::Result<()> {
+    // One row per species with its final abundance and extinction time after the
+    // outcome of the run, in the columns of a batch's runs.csv, with the run metadata in
+    // a sidecar file
+
+    let mut file = BufWriter::new(File::create(path)?);
+    writeln!(
+        file,
+        "stop_reason,t,steps,species,name,abundance,extinction_t"
+    )?;
+    for (id, name, abundance, extinction) in &outcome.species {
+        let extinction = extinction.map_or(String::new(), |t| t.to_string());
+        writeln!(
+            file,
+            "{:?},{},{},{id},{},{abundance},{extinction}",
+            outcome.reason,
+            outcome.t,
+            outcome.steps,
+            csv_field(name)
+        )?;
+    }
+    file.flush()?;
+    population.metadata().write_sidecar(path)
+}
+
+fn simulate_one(
+    population: &mut Population,
+    max_t: f64,
+    out: Option<&Path>,
+    progress: bool,
+    watches: &[Condition],
+) -> Result<Outcome, String> {
+    // Run a population on to max_t, writing its outputs into out, or its history to
+    // stdout, and report on stderr whenever a watched condition starts to hold
+
+    for condition in watches {
+        population.watch(condition.clone(), |t, condition| {
+            eprintln!("\rt = {t:.3}: {condition}");
+        });
+    }
+    let mut events = match out {
+        Some(dir) => {
+            fs::create_dir_all(dir).map_err(|e| format!("{}: {e}", dir.display()))?;
+            let path = dir.join("events.ndjson");
+            let file = File::create(&path).map_err(|e| format!("{}: {e}", path.display()))?;
+            Some(BufWriter::new(file))
+        }
+        None => None,
+    };
+    let mut bar = ProgressBar {
+        start: population.t,
+        end: max_t,
+        drawn: None,
+    };
+    let mut steps = 0;
+    // the first failed write, reported once the run is over
+    let mut failure: Option<io::Error> = None;
+    let reason = population
+        .simulate_with(max_t, |population, record| {
+            steps += 1;
+            if progress {
+                bar.update(population.t, population.size);
+            }
+            if let (Some(writer), Some(record), None) = (events.as_mut(), record, &failure) {
+                failure = writeln!(writer, "{}", event_json(record)).err();
+            }
+        })
+        .map_err(|e| e.to_string())?;
+    bar.finish();
+    if let Some(writer) = events.as_mut() {
+        failure = failure.or(writer.flush().err());
+    }
+    if let Some(e) = failure {
+        return Err(format!("writing events: {e}"));
+    }
+    if let Some(dir) = out {
+        let path = dir.join("events.ndjson");
+        population
+            .metadata()
+            .write_sidecar(&path)
+            .map_err(|e| format!("{}: {e}", path.display()))?;
+    }
+
+    let outcome = outcome(population, reason, steps);
+    match out {
+        Some(dir) => {
+            let path = dir.join("history.csv");
+            population
+                .write_csv(&path)
+                .map_err(|e| format!("{}: {e}", path.display()))?;
+            let path = dir.join("summary.csv");
+            write_summary(population, &outcome, &path)
+                .map_err(|e| format!("{}: {e}", path.display()))?;
+            if population.history.heatmap.is_some() {
+                let path = dir.join("heatmap.csv");
+                File::create(&path)
+                    .and_then(|file| {
+                        let mut file = BufWriter::new(file);
+                        population.history.heatmap_to_csv(&mut file)?;
+                        file.flush()?;
+                        population.metadata().write_sidecar(&path)
+                    })
+                    .map_err(|e| format!("{}: {e}", path.display()))?;
+            }
+        }
+        None => population
+            .history
+            .to_csv(io::stdout().lock())
+            .map_err(|e| e.to_string())?,
+    }
+    Ok(outcome)
+}
+
+fn report(outcome: &Outcome) {
+    let size: usize = outcome.species.iter().map(|x| x.2).sum();
+    eprintln!(
+        "seed {}: stopped at t = {:.3} ({:?}) after {} steps with {size} individuals",
+        outcome.seed, outcome.t, outcome.reason, outcome.steps
+    );
+}
+
+pub fn run(options: &Options) -> Result<(), String> {
+    // Run the simulation (or batch of simulations) described by the options, writing
+    // their outputs and reporting how each ended on stderr
+
+    let mut species = load_species(&options.species)
+        .map_err(|e| format!("{}: {e}", options.species.display()))?;
+    let files = match &options.config {
+        Some(path) => read_toml(path).map_err(|e| e.to_string())?,
+        None => vec![],
+    };
+    for (path, text) in &files {
+        apply_species_toml(&mut species, text).map_err(|e| format!("{}: {e}", path.display()))?;
+    }
+    let mut config = SimulationConfig::new(species.iter().collect(), f64::NAN);
+    for (path, text) in &files {
+        config
+            .apply_toml(text)
+            .map_err(|e| format!("{}: {e}", path.display()))?;
+    }
+    config.max_t = options.until.unwrap_or(config.max_t);
+    if config.max_t.is_nan() {
+        return Err("no final time: pass --until or set max_t in the config".to_string());
+    }
+    config.seed = options.seed.or(config.seed);
+    config.heatmap = options.heatmap.or(config.heatmap);
+
+    if options.command == Command::Profile {
+        let mut population = Population::from_config(&config).map_err(|e| e.to_string())?;
+        let calibration = options.calibration.unwrap_or(config.max_t.min(1.0));
+        println!("{}", population.profile(calibration, config.max_t));
+        return Ok(());
+    }
+    if options.command == Command::Replay {
+        let seed = config.seed.unwrap_or_else(rand::random);
+        config.seed = Some(seed);
+        let steps = verify_config_replay(&config).map_err(|e| format!("seed {seed}: {e}"))?;
+        eprintln!("seed {seed}: both runs agree over {steps} steps");
+        return Ok(());
+    }
+    if options.command == Command::Analyze {
+        let seed_start = options
+            .seed_start
+            .or(config.seed)
+            .unwrap_or_else(rand::random);
+        let seeds: Vec<u64> = (0..options.replicates as u64)
+            .map(|r| seed_start.wrapping_add(r))
+            .collect();
+        let envelope = pcf_envelope(
+            &config,
+            &seeds,
+            options.max_r.unwrap_or(0.25),
+            options.bins.unwrap_or(25),
+            options.level.unwrap_or(0.95),
+        )
+        .map_err(|e| e.to_string())?;
+        println!("r,mean,lower,upper");
+        for k in 0..envelope.radii.len() {
+            println!(
+                "{},{},{},{}",
+                envelope.radii[k], envelope.mean[k], envelope.lower[k], envelope.upper[k]
+            );
+        }
+        return Ok(());
+    }
+    if let (Some(dir), false) = (&options.out, files.is_empty()) {
+        let texts: Vec<&str> = files.iter().map(|(_, text)| text.as_str()).collect();
+        let path = dir.join("config.toml");
+        fs::create_dir_all(dir)
+            .and_then(|_| fs::write(&path, merge_toml(&texts)))
+            .map_err(|e| format!("{}: {e}", path.display()))?;
+    }
+    if !options.is_batch() {
+        let mut population = Population::from_config(&config).map_err(|e| e.to_string())?;
+        if let Some(path) = &options.resume {
+            population
+                .resume_from(path)
+                .map_err(|e| format!("{}: {e}", path.display()))?;
+        }
+        let outcome = simulate_one(
+            &mut population,
+            config.max_t,
+            options.out.as_deref(),
+            !options.quiet,
+            &options.watches,
+        )?;
+        if let Some(path) = &options.save_state {
+            population
+                .save_state(path)
+                .map_err(|e| format!("{}: {e}", path.display()))?;
+        }
+        report(&outcome);
+        return Ok(());
+    }
+    run_batch(options, &config)
+}
+
+fn run_batch(options: &Options, config: &SimulationConfig) -> Result<(), String> {
+    // Runs are numbered with the sweep combination varying slowest and the replicate
+    // fastest; replicate r is seeded seed_start + r in every combination, so that
+    // combinations differ only in their parameters. Each run writes into out/run-<k>,
+    // and out/runs.csv (or runs-<k>.csv with --only) collects their outcomes.
+
+    let out = options.out.as_deref().expect("checked by parse_args");
+    let seed_start = options
+        .seed_start
+        .or(config.seed)
+        .unwrap_or_else(rand::random);
+    let combinations: usize = options.sweeps.iter().map(|x| x.values.len()).product();
+    let runs = combinations * options.replicates;
+    if let Some(k) = options.only.filter(|k| *k >= runs) {
+        return Err(format!("--only {k}: there are only {runs} runs"));
+    }
+    let axes = options
+        .sweeps
+        .iter()
+        .map(|sweep| sweep_axis(sweep, config))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    fs::create_dir_all(out).map_err(|e| format!("{}: {e}", out.display()))?;
+    let path = match options.only {
+        Some(k) => out.join(format!("runs-{k}.csv")),
+        None => out.join("runs.csv"),
+    };
+    let mut table =
+        BufWriter::new(File::create(&path).map_err(|e| format!("{}: {e}", path.display()))?);
+    let columns: String = axes.iter().map(|x| format!(",{}", x.name)).collect();
+    let table_error = |e: io::Error| format!("{}: {e}", path.display());
+    writeln!(
+        table,
+        "run,seed{columns},stop_reason,t,steps,species,name,abundance,extinction_t"
+    )
+    .map_err(table_error)?;
+
+    sweep_with(
+        config,
+        &axes,
+        |combination, values, swept| -> Result<(), String> {
+            for replicate in 0..options.replicates {
+                let k = combination * options.replicates + replicate;
+                if options.only.is_some_and(|only| only != k) {
+                    continue;
+                }
+                let mut run_config = swept.clone();
+                run_config.seed = Some(seed_start.wrapping_add(replicate as u64));
+
+                let dir = out.join(format!("run-{k}"));
+                let outcome = Population::from_config(&run_config)
+                    .map_err(|e| e.to_string())
+                    .and_then(|mut population| {
+                        simulate_one(
+                            &mut population,
+                            run_config.max_t,
+                            Some(&dir),
+                            false,
+                            &options.watches,
+                        )
+                    })
+                    .map_err(|e| format!("run {k}: {e}"))?;
+                if !options.quiet {
+                    eprint!("run {k}: ");
+                    report(&outcome);
+                }
+                let values: String = values.iter().map(|x| format!(",{x}")).collect();
+                for (id, name, abundance, extinction) in &outcome.species {
+                    let extinction = extinction.map_or(String::new(), |t| t.to_string());
+                    writeln!(
+                        table,
+                        "{k},{}{values},{:?},{},{},{id},{},{abundance},{extinction}",
+                        outcome.seed,
+                        outcome.reason,
+                        outcome.t,
+                        outcome.steps,
+                        csv_field(name)
+                    )
+                    .map_err(table_error)?;
+                }
+            }
+            Ok(())
+        },
+    )?;
+    table.flush().map_err(table_error)
+}
+
+fn sweep_axis(sweep: &Sweep, config: &SimulationConfig) -> Result<SweepAxis, String> {
+    // the sweep of a species field named on the command line, over every species when
+    // none is given
+
+    let (name, species) = match sweep.species {
+        Some(id) => {
+            let idx = config
+                .species
+                .iter()
+                .position(|x| x.id == id)
+                .ok_or_else(|| format!("--sweep: there is no species {id}"))?;
+            (format!("{id}.{}", sweep.field), Some(idx))
+        }
+        None => (sweep.field.clone(), None),
+    };
+    let field = sweep.field.clone();
+    Ok(SweepAxis {
+        name,
+        species,
+        values: sweep.values.clone(),
+        set: Box::new(move |species, value| {
+            *f64_field(species, &field).expect("checked by parse_sweep") = value;
+        }),
+    })
+}
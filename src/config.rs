@@ -0,0 +1,558 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::habitat::Habitat;
+use crate::heatmap::Kde;
+use crate::history::{History, Precision, Stationarity};
+use crate::loader::{set_field, strip_comment};
+use crate::metadata::settings_hash;
+use crate::obstacle::Obstacle;
+use crate::resource::Resource;
+use crate::schedule::{Harvest, Introduction};
+use crate::{CapPolicy, Population, SimError, Species, Stepping};
+
+// everything needed to reproduce a run; the domain is always the unit torus
+#[derive(Clone)]
+pub struct SimulationConfig<'a> {
+    pub species: Vec<&'a Species>,
+    // drawn from the thread rng when absent
+    pub seed: Option<u64>,
+    pub max_t: f64,
+    pub burn_in: f64,
+    // minimum simulated time between recorded checkpoints
+    pub checkpoint_interval: f64,
+    // exact times at which to checkpoint, instead of after every event
+    pub output_times: Option<Vec<f64>>,
+    pub precision: Precision,
+    // keep a log of changes after the first checkpoint instead of further checkpoints
+    pub event_log: bool,
+    // density estimate attached to each checkpoint
+    pub heatmap: Option<Kde>,
+    pub size_cap: Option<(usize, CapPolicy)>,
+    pub stepping: Stepping,
+    pub stationarity: Option<Stationarity>,
+    // rasters scaling birth and death rates by location
+    pub birth_habitat: Option<Habitat>,
+    pub death_habitat: Option<Habitat>,
+    // a consumed resource limiting births, starting at capacity
+    pub resource: Option<Resource>,
+    // regions no individual is placed in, born into or moves into
+    pub obstacles: Vec<Obstacle>,
+    pub harvests: Vec<Harvest>,
+    // releases of founders; each follows the species of the same id in `species`
+    pub introductions: Vec<Introduction<'a>>,
+}
+
+impl<'a> SimulationConfig<'a> {
+    pub fn new(species: Vec<&'a Species>, max_t: f64) -> Self {
+        SimulationConfig {
+            species,
+            seed: None,
+            max_t,
+            burn_in: 0.0,
+            checkpoint_interval: 0.0,
+            output_times: None,
+            precision: Precision::Double,
+            event_log: false,
+            heatmap: None,
+            size_cap: None,
+            stepping: Stepping::Exact,
+            stationarity: None,
+            birth_habitat: None,
+            death_habitat: None,
+            resource: None,
+            obstacles: vec![],
+            harvests: vec![],
+            introductions: vec![],
+        }
+    }
+
+    pub fn apply_toml(&mut self, text: &str) -> io::Result<()> {
+        // Override settings from `key = value` lines, optionally under a [simulation]
+        // table: seed, max_t, burn_in, checkpoint_interval, output_times (an array),
+        // precision ("single" or "double"), event_log (true or false), heatmap
+        // ([resolution, bandwidth], optionally followed by a species id), size_cap with
+        // cap_policy ("stop" or "throttle"), tau_leap (the epsilon of a tau-leaping run),
+        // stationarity_window with stationarity_tolerance, birth_habitat and
+        // death_habitat (paths of rasters read by Habitat::load), resource ([resolution,
+        // capacity, growth, consumption]), and any number of obstacle ([x_min, y_min,
+        // x_max, y_max]), obstacle_polygon ([x1, y1, x2, y2, ...]), harvest ([t, species
+        // id, fraction], optionally followed by the x_min, y_min, x_max, y_max of the
+        // region culled) and introduction ([t, species id, founders, x, y, spread]) lines.
+        // [species.<id>] tables are left to apply_species_toml, and extends lines to
+        // read_toml
+
+        let invalid = |number: usize, message: String| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("line {}: {message}", number + 1),
+            )
+        };
+        let mut cap = None;
+        let mut policy = CapPolicy::Stop;
+        let (mut window, mut tolerance) = (None, None);
+        let mut in_species = false;
+        for (number, line) in text.lines().enumerate() {
+            let line = strip_comment(line);
+            if line.starts_with('[') {
+                in_species = match (line, species_table(line)) {
+                    ("[simulation]", _) => false,
+                    (_, Some(_)) => true,
+                    _ => return Err(invalid(number, format!("unknown table {line}"))),
+                };
+                continue;
+            }
+            if line.is_empty() || in_species {
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(invalid(number, "expected a `key = value` line".into()));
+            };
+            let (key, value) = (key.trim(), value.trim().trim_matches('"'));
+            let number_value = || {
+                value
+                    .parse::<f64>()
+                    .map_err(|_| invalid(number, format!("{key} must be a number, got {value}")))
+            };
+            let count_value = || {
+                value.parse::<u64>().map_err(|_| {
+                    invalid(
+                        number,
+                        format!("{key} must be a non-negative integer, got {value}"),
+                    )
+                })
+            };
+            let number_array = || {
+                number_list(value).ok_or_else(|| {
+                    invalid(
+                        number,
+                        format!("{key} must be an array of numbers, got {value}"),
+                    )
+                })
+            };
+            let habitat =
+                || Habitat::load(value).map_err(|e| invalid(number, format!("{key} {value}: {e}")));
+            match key {
+                "seed" => self.seed = Some(count_value()?),
+                "max_t" => self.max_t = number_value()?,
+                "burn_in" => self.burn_in = number_value()?,
+                "checkpoint_interval" => self.checkpoint_interval = number_value()?,
+                "output_times" => self.output_times = Some(number_array()?),
+                "precision" => {
+                    self.precision = match value {
+                        "single" => Precision::Single,
+                        "double" => Precision::Double,
+                        _ => return Err(invalid(number, format!("unknown precision {value}"))),
+                    }
+                }
+                "event_log" => {
+                    self.event_log = match value {
+                        "true" => true,
+                        "false" => false,
+                        _ => {
+                            return Err(invalid(
+                                number,
+                                format!("event_log must be true or false, got {value}"),
+                            ))
+                        }
+                    }
+                }
+                "heatmap" => {
+                    self.heatmap = Some(parse_kde(&number_array()?).ok_or_else(|| {
+                        invalid(
+                            number,
+                            format!(
+                                "heatmap must be [resolution, bandwidth] with an optional \
+                                 species id, got {value}"
+                            ),
+                        )
+                    })?)
+                }
+                "size_cap" => cap = Some(count_value()? as usize),
+                "cap_policy" => {
+                    policy = match value {
+                        "stop" => CapPolicy::Stop,
+                        "throttle" => CapPolicy::Throttle,
+                        _ => return Err(invalid(number, format!("unknown cap_policy {value}"))),
+                    }
+                }
+                "tau_leap" => {
+                    self.stepping = Stepping::TauLeap {
+                        epsilon: number_value()?,
+                    }
+                }
+                "stationarity_window" => window = Some(number_value()?),
+                "stationarity_tolerance" => tolerance = Some(number_value()?),
+                "birth_habitat" => self.birth_habitat = Some(habitat()?),
+                "death_habitat" => self.death_habitat = Some(habitat()?),
+                "resource" => match number_array()?[..] {
+                    [resolution, capacity, growth, consumption]
+                        if resolution >= 1.0
+                            && resolution.fract() == 0.0
+                            && [capacity, growth, consumption]
+                                .iter()
+                                .all(|x| x.is_finite() && *x >= 0.0) =>
+                    {
+                        self.resource = Some(Resource::new(
+                            resolution as usize,
+                            capacity,
+                            growth,
+                            consumption,
+                        ))
+                    }
+                    _ => {
+                        return Err(invalid(
+                            number,
+                            format!(
+                                "resource must be [resolution, capacity, growth, consumption], \
+                                 got {value}"
+                            ),
+                        ))
+                    }
+                },
+                "obstacle" => match number_array()?[..] {
+                    [x_min, y_min, x_max, y_max] if x_min < x_max && y_min < y_max => {
+                        self.obstacles.push(Obstacle::Rectangle {
+                            x_min,
+                            y_min,
+                            x_max,
+                            y_max,
+                        })
+                    }
+                    _ => {
+                        return Err(invalid(
+                            number,
+                            format!("obstacle must be [x_min, y_min, x_max, y_max], got {value}"),
+                        ))
+                    }
+                },
+                "obstacle_polygon" => {
+                    let coordinates = number_array()?;
+                    if coordinates.len() < 6 || coordinates.len() % 2 != 0 {
+                        return Err(invalid(
+                            number,
+                            format!(
+                                "obstacle_polygon needs at least three x, y pairs, got {value}"
+                            ),
+                        ));
+                    }
+                    let vertices = coordinates.chunks(2).map(|p| (p[0], p[1])).collect();
+                    self.obstacles.push(Obstacle::Polygon(vertices));
+                }
+                "harvest" => {
+                    let values = number_array()?;
+                    let region = match values[..] {
+                        [_, _, _] => None,
+                        [_, _, _, x_min, y_min, x_max, y_max] => Some([x_min, y_min, x_max, y_max]),
+                        _ => {
+                            return Err(invalid(
+                                number,
+                                format!(
+                                    "harvest must be [t, species, fraction] with an optional \
+                                     region, got {value}"
+                                ),
+                            ))
+                        }
+                    };
+                    let (t, species, fraction) = (values[0], values[1], values[2]);
+                    if !(0.0..=255.0).contains(&species)
+                        || species.fract() != 0.0
+                        || !(0.0..=1.0).contains(&fraction)
+                    {
+                        return Err(invalid(
+                            number,
+                            format!(
+                                "harvest needs a species id and a fraction in [0, 1], got {value}"
+                            ),
+                        ));
+                    }
+                    self.harvests.push(Harvest {
+                        t,
+                        species: species as u8,
+                        fraction,
+                        region,
+                    });
+                }
+                "introduction" => match number_array()?[..] {
+                    [t, id, founders, x, y, spread]
+                        if founders >= 0.0
+                            && founders.fract() == 0.0
+                            && (0.0..1.0).contains(&x)
+                            && (0.0..1.0).contains(&y)
+                            && spread >= 0.0 =>
+                    {
+                        let Some(&species) = self.species.iter().find(|s| s.id as f64 == id) else {
+                            return Err(invalid(number, format!("no species with id {id}")));
+                        };
+                        self.introductions.push(Introduction {
+                            t,
+                            species,
+                            founders: founders as usize,
+                            location: (x, y),
+                            spread,
+                        });
+                    }
+                    _ => {
+                        return Err(invalid(
+                            number,
+                            format!(
+                                "introduction must be [t, species, founders, x, y, spread] \
+                                 with the release point in the unit square, got {value}"
+                            ),
+                        ))
+                    }
+                },
+                "extends" => {
+                    return Err(invalid(
+                        number,
+                        "extends is only followed in files read by read_toml".into(),
+                    ))
+                }
+                _ => return Err(invalid(number, format!("unknown setting {key}"))),
+            }
+        }
+        if let Some(limit) = cap {
+            self.size_cap = Some((limit, policy));
+        }
+        match (window, tolerance) {
+            (Some(window), Some(tolerance)) => {
+                self.stationarity = Some(Stationarity { window, tolerance })
+            }
+            (None, None) => (),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "stationarity_window and stationarity_tolerance must be given together",
+                ))
+            }
+        }
+        Ok(())
+    }
+}
+
+// settings given any number of times, each adding to the ones before instead of
+// replacing them
+const REPEATED: [&str; 4] = ["obstacle", "obstacle_polygon", "harvest", "introduction"];
+
+fn species_table(header: &str) -> Option<u8> {
+    // the species id of a [species.<id>] table header
+    header
+        .strip_prefix("[species.")?
+        .strip_suffix(']')?
+        .trim()
+        .parse()
+        .ok()
+}
+
+pub fn read_toml(path: impl AsRef<Path>) -> io::Result<Vec<(PathBuf, String)>> {
+    // The texts of a config file and of the chain of files it extends through an
+    // `extends = "base.toml"` line, base first, so that applying them in turn lets each
+    // file override the one it extends. Paths are relative to the extending file, and
+    // extends lines are blanked out to keep line numbers in errors right
+
+    let mut files = vec![];
+    let mut seen = vec![];
+    let mut next = Some(path.as_ref().to_path_buf());
+    while let Some(path) = next.take() {
+        let annotate = |e: io::Error| io::Error::new(e.kind(), format!("{}: {e}", path.display()));
+        let canonical = fs::canonicalize(&path).map_err(annotate)?;
+        if seen.contains(&canonical) {
+            return Err(annotate(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "extends itself",
+            )));
+        }
+        seen.push(canonical);
+        let text = fs::read_to_string(&path).map_err(annotate)?;
+        let mut own = String::new();
+        let mut table = false;
+        for line in text.lines() {
+            let setting = strip_comment(line);
+            if setting.starts_with('[') {
+                table = setting != "[simulation]";
+            }
+            match setting.split_once('=') {
+                Some((key, value)) if key.trim() == "extends" && !table => {
+                    let base = value.trim().trim_matches('"');
+                    next = Some(path.parent().unwrap_or(Path::new("")).join(base));
+                }
+                _ => own.push_str(line),
+            }
+            own.push('\n');
+        }
+        files.push((path, own));
+    }
+    files.reverse();
+    Ok(files)
+}
+
+pub fn merge_toml(texts: &[&str]) -> String {
+    // Config texts applied in turn as a single file: each setting at its last value,
+    // save for repeated ones such as obstacles, which accumulate; settings keep the
+    // order they were first given in, with species tables after the rest
+
+    let mut settings: Vec<(Option<&str>, &str, &str)> = vec![];
+    for text in texts {
+        let mut table = None;
+        for line in text.lines() {
+            let line = strip_comment(line);
+            if line.starts_with('[') {
+                table = (line != "[simulation]").then_some(line);
+                continue;
+            }
+            let Some((key, _)) = line.split_once('=') else {
+                continue;
+            };
+            let key = key.trim();
+            let replaced = settings.iter_mut().find(|(t, k, _)| {
+                *t == table && *k == key && !(table.is_none() && REPEATED.contains(&key))
+            });
+            match replaced {
+                Some(setting) => setting.2 = line,
+                None => settings.push((table, key, line)),
+            }
+        }
+    }
+
+    let mut tables = vec![None];
+    for (table, _, _) in &settings {
+        if !tables.contains(table) {
+            tables.push(*table);
+        }
+    }
+    let mut merged = String::new();
+    for table in tables {
+        if let Some(header) = table {
+            merged += &format!("\n{header}\n");
+        }
+        for (_, _, line) in settings.iter().filter(|x| x.0 == table) {
+            merged += &format!("{line}\n");
+        }
+    }
+    merged
+}
+
+pub fn apply_species_toml(species: &mut [Species], text: &str) -> io::Result<()> {
+    // Override the fields of species from the `key = value` lines of [species.<id>]
+    // tables, named and written as in a species file; everything outside those tables
+    // is left to SimulationConfig::apply_toml
+
+    let invalid = |number: usize, message: String| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("line {}: {message}", number + 1),
+        )
+    };
+    let mut table = None;
+    for (number, line) in text.lines().enumerate() {
+        let line = strip_comment(line);
+        if line.starts_with('[') {
+            table = match species_table(line) {
+                Some(id) => match species.iter().position(|x| x.id == id) {
+                    Some(idx) => Some(idx),
+                    None => return Err(invalid(number, format!("no species with id {id}"))),
+                },
+                None => None,
+            };
+            continue;
+        }
+        let Some(idx) = table.filter(|_| !line.is_empty()) else {
+            continue;
+        };
+        let Some((key, value)) = line.split_once('=') else {
+            return Err(invalid(number, "expected a `key = value` line".into()));
+        };
+        let (key, value) = (key.trim(), value.trim().trim_matches('"'));
+        if key == "id" {
+            return Err(invalid(
+                number,
+                "the id of a species cannot be overridden".into(),
+            ));
+        }
+        set_field(&mut species[idx], key, value).map_err(|e| invalid(number, e))?;
+    }
+    for species in species.iter() {
+        species.validate().map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("species {}: {e}", species.id),
+            )
+        })?;
+    }
+    Ok(())
+}
+
+fn number_list(value: &str) -> Option<Vec<f64>> {
+    // the numbers of a `[a, b, ...]` array, or None if it is not one
+    value
+        .strip_prefix('[')?
+        .strip_suffix(']')?
+        .split(',')
+        .map(str::trim)
+        .filter(|x| !x.is_empty())
+        .map(|x| x.parse::<f64>().ok())
+        .collect()
+}
+
+pub(crate) fn parse_kde(values: &[f64]) -> Option<Kde> {
+    // a positive whole resolution and a non-negative bandwidth, then optionally a species
+    let (&resolution, &bandwidth, species) = match values {
+        [resolution, bandwidth] => (resolution, bandwidth, None),
+        [resolution, bandwidth, species] => (resolution, bandwidth, Some(*species)),
+        _ => return None,
+    };
+    let whole = |x: f64, max: f64| (0.0..=max).contains(&x) && x.fract() == 0.0;
+    if !whole(resolution, u32::MAX as f64)
+        || resolution == 0.0
+        || !(bandwidth.is_finite() && bandwidth >= 0.0)
+        || species.is_some_and(|s| !whole(s, u8::MAX as f64))
+    {
+        return None;
+    }
+    Some(Kde {
+        resolution: resolution as usize,
+        bandwidth,
+        species: species.map(|s| s as u8),
+    })
+}
+
+impl<'a> Population<'a> {
+    pub fn from_config(config: &SimulationConfig<'a>) -> Result<Self, SimError> {
+        let seed = config.seed.unwrap_or_else(rand::random);
+        let mut population =
+            Population::with_obstacles(config.species.clone(), seed, config.obstacles.clone())?;
+        population.burn_in = config.burn_in;
+        population.checkpoint_interval = config.checkpoint_interval;
+        population.output_times = config.output_times.clone().map(|mut times| {
+            times.sort_by(|a, b| a.total_cmp(b));
+            times
+        });
+        population.settings_hash = settings_hash(config);
+        population.history = History::new(config.precision);
+        population.set_event_log(config.event_log);
+        population.set_heatmap(config.heatmap);
+        population.size_cap = config.size_cap;
+        population.stepping = config.stepping;
+        population.stationarity = config.stationarity;
+        population.set_habitat(config.birth_habitat.clone(), config.death_habitat.clone());
+        population.set_resource(config.resource.clone());
+        for harvest in &config.harvests {
+            population.schedule_harvest(harvest.clone());
+        }
+        for introduction in &config.introductions {
+            // a sweep may have replaced the species the introduction was read against
+            let species = config
+                .species
+                .iter()
+                .find(|x| x.id == introduction.species.id)
+                .copied()
+                .unwrap_or(introduction.species);
+            population.schedule_introduction(Introduction {
+                species,
+                ..introduction.clone()
+            });
+        }
+        Ok(population)
+    }
+}
@@ -0,0 +1,95 @@
+use std::error;
+use std::fmt;
+use std::io;
+
+use crate::golden::GoldenMismatch;
+use crate::pattern::OutOfDomain;
+use crate::replay::Divergence;
+use crate::species::SpeciesError;
+use crate::Event;
+
+#[derive(Debug)]
+pub enum SimError {
+    Species(SpeciesError),
+    OutOfDomain(OutOfDomain),
+    // an individual's event rate became negative or non-finite during a run
+    InvalidRate { uid: usize, event: Event, rate: f64 },
+    Diverged(Divergence),
+    Golden(GoldenMismatch),
+    Io(io::Error),
+    // the population's bookkeeping disagrees with a recomputation
+    Invariant(String),
+    // an initial position of the species falls inside an obstacle and cannot be redrawn
+    Obstructed { species: u8, x: f64, y: f64 },
+    // a fit given unusable priors or observations, or left with no accepted draws
+    Fit(String),
+}
+
+impl fmt::Display for SimError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SimError::Species(err) => write!(f, "invalid species: {err}"),
+            SimError::OutOfDomain(err) => write!(f, "invalid coordinates: {err}"),
+            SimError::InvalidRate { uid, event, rate } => {
+                write!(f, "individual {uid} has invalid {event:?} rate {rate}")
+            }
+            SimError::Diverged(err) => write!(f, "{err}"),
+            SimError::Golden(err) => write!(f, "{err}"),
+            SimError::Io(err) => write!(f, "{err}"),
+            SimError::Invariant(message) => write!(f, "invariant violated: {message}"),
+            SimError::Obstructed { species, x, y } => {
+                write!(
+                    f,
+                    "species {species} cannot start at ({x}, {y}) inside an obstacle"
+                )
+            }
+            SimError::Fit(message) => write!(f, "cannot fit: {message}"),
+        }
+    }
+}
+
+impl error::Error for SimError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            SimError::Species(err) => Some(err),
+            SimError::OutOfDomain(err) => Some(err),
+            SimError::Diverged(err) => Some(err),
+            SimError::Golden(err) => Some(err),
+            SimError::Io(err) => Some(err),
+            SimError::InvalidRate { .. }
+            | SimError::Invariant(_)
+            | SimError::Obstructed { .. }
+            | SimError::Fit(_) => None,
+        }
+    }
+}
+
+impl From<SpeciesError> for SimError {
+    fn from(err: SpeciesError) -> Self {
+        SimError::Species(err)
+    }
+}
+
+impl From<OutOfDomain> for SimError {
+    fn from(err: OutOfDomain) -> Self {
+        SimError::OutOfDomain(err)
+    }
+}
+
+impl From<Divergence> for SimError {
+    fn from(err: Divergence) -> Self {
+        SimError::Diverged(err)
+    }
+}
+
+impl From<io::Error> for SimError {
+    fn from(err: io::Error) -> Self {
+        SimError::Io(err)
+    }
+}
+
+impl From<GoldenMismatch> for SimError {
+    fn from(err: GoldenMismatch) -> Self {
+        SimError::Golden(err)
+    }
+}
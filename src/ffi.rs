@@ -0,0 +1,221 @@
+use std::cell::RefCell;
+use std::ffi::{c_char, CStr, CString};
+use std::mem::ManuallyDrop;
+use std::ptr;
+
+use crate::config::{apply_species_toml, SimulationConfig};
+use crate::loader::species_from_toml;
+use crate::{Population, Species};
+
+// C interface for driving a simulation from R, Julia, C++ and the like, built as a
+// shared library with
+//
+//     cargo rustc --lib --release --features ffi --crate-type cdylib
+//
+// Ownership: popdyn_create returns a simulation owned by the caller, who must release
+// it with popdyn_free exactly once and not use it afterwards. Every other function
+// borrows the simulation for the duration of the call only, and none of them may be
+// called on the same simulation from two threads at once. Strings passed in are
+// borrowed and copied; the string from popdyn_last_error belongs to the library and
+// stays valid until the next failing call on the same thread. Output buffers are
+// owned by the caller.
+
+thread_local! {
+    static LAST_ERROR: RefCell<CString> = RefCell::new(CString::default());
+}
+
+fn fail(message: impl Into<String>) {
+    // interior nul bytes cannot occur in our messages, but are dropped if they do
+    let message = CString::new(message.into().replace('\0', "")).unwrap_or_default();
+    LAST_ERROR.with(|x| *x.borrow_mut() = message);
+}
+
+// a population together with the species it borrows, which are freed after it
+pub struct Simulation {
+    population: ManuallyDrop<Population<'static>>,
+    species: *mut [Species],
+}
+
+impl Drop for Simulation {
+    fn drop(&mut self) {
+        unsafe {
+            ManuallyDrop::drop(&mut self.population);
+            drop(Box::from_raw(self.species));
+        }
+    }
+}
+
+unsafe fn text<'s>(value: *const c_char, what: &str) -> Result<&'s str, String> {
+    if value.is_null() {
+        return Err(format!("{what} is null"));
+    }
+    CStr::from_ptr(value)
+        .to_str()
+        .map_err(|_| format!("{what} is not valid UTF-8"))
+}
+
+unsafe fn create(species: *const c_char, config: *const c_char) -> Result<Simulation, String> {
+    let mut species = species_from_toml(text(species, "species")?).map_err(|e| e.to_string())?;
+    let mut settings = SimulationConfig::new(vec![], f64::INFINITY);
+    if !config.is_null() {
+        let config = text(config, "config")?;
+        apply_species_toml(&mut species, config).map_err(|e| e.to_string())?;
+        settings.apply_toml(config).map_err(|e| e.to_string())?;
+    }
+
+    // the species live on the heap until the simulation is dropped
+    let species: *mut [Species] = Box::into_raw(species.into_boxed_slice());
+    settings.species = (*species).iter().collect();
+    match Population::from_config(&settings) {
+        Ok(population) => Ok(Simulation {
+            population: ManuallyDrop::new(population),
+            species,
+        }),
+        Err(e) => {
+            drop(settings);
+            drop(Box::from_raw(species));
+            Err(e.to_string())
+        }
+    }
+}
+
+/// Create a simulation from species parameters in the TOML format read by the species
+/// loader and optional run settings in the format of `SimulationConfig::apply_toml`;
+/// returns null on failure, with the reason in `popdyn_last_error`.
+///
+/// # Safety
+///
+/// `species` must be a nul-terminated string and `config` one or null.
+#[no_mangle]
+pub unsafe extern "C" fn popdyn_create(
+    species: *const c_char,
+    config: *const c_char,
+) -> *mut Simulation {
+    match create(species, config) {
+        Ok(simulation) => Box::into_raw(Box::new(simulation)),
+        Err(message) => {
+            fail(message);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// The message of the last failure on this thread, or an empty string.
+#[no_mangle]
+pub extern "C" fn popdyn_last_error() -> *const c_char {
+    LAST_ERROR.with(|x| x.borrow().as_ptr())
+}
+
+/// Advance by up to `steps` events (or leaps, when tau-leaping), checkpointing as the
+/// simulation would; returns the number taken, fewer once nothing more can happen, or
+/// -1 on failure.
+///
+/// # Safety
+///
+/// `simulation` must come from `popdyn_create` and not have been freed.
+#[no_mangle]
+pub unsafe extern "C" fn popdyn_step(simulation: *mut Simulation, steps: u64) -> i64 {
+    let Some(simulation) = simulation.as_mut() else {
+        fail("simulation is null");
+        return -1;
+    };
+    let population = &mut *simulation.population;
+    if population.history.checkpoints.is_empty() {
+        population.record_checkpoint();
+    }
+    let mut taken = 0;
+    while taken < steps {
+        match population.progress(f64::INFINITY) {
+            Ok(true) => (),
+            Ok(false) => break,
+            Err(e) => {
+                fail(e.to_string());
+                return -1;
+            }
+        }
+        population.record_checkpoint();
+        population.check_watchers();
+        taken += 1;
+    }
+    taken as i64
+}
+
+/// Current simulated time, or NaN for a null simulation.
+///
+/// # Safety
+///
+/// `simulation` must come from `popdyn_create` and not have been freed.
+#[no_mangle]
+pub unsafe extern "C" fn popdyn_time(simulation: *const Simulation) -> f64 {
+    simulation.as_ref().map_or(f64::NAN, |x| x.population.t)
+}
+
+/// Number of living individuals.
+///
+/// # Safety
+///
+/// `simulation` must come from `popdyn_create` and not have been freed.
+#[no_mangle]
+pub unsafe extern "C" fn popdyn_size(simulation: *const Simulation) -> usize {
+    simulation.as_ref().map_or(0, |x| x.population.size)
+}
+
+/// Write the positions of up to `capacity` individuals as x, y pairs into `xy`, which
+/// must hold 2 * `capacity` doubles; returns the number of individuals, so a result
+/// above `capacity` means the buffer was too small.
+///
+/// # Safety
+///
+/// `simulation` must come from `popdyn_create` and not have been freed, and `xy` must
+/// be valid for 2 * `capacity` writes (or null with `capacity` 0).
+#[no_mangle]
+pub unsafe extern "C" fn popdyn_get_positions(
+    simulation: *const Simulation,
+    xy: *mut f64,
+    capacity: usize,
+) -> usize {
+    let Some(simulation) = simulation.as_ref() else {
+        return 0;
+    };
+    let individuals = &simulation.population.individuals;
+    for (idx, individual) in individuals.iter().take(capacity).enumerate() {
+        *xy.add(2 * idx) = individual.x_coord;
+        *xy.add(2 * idx + 1) = individual.y_coord;
+    }
+    individuals.len()
+}
+
+/// Write the species ids of up to `capacity` individuals into `ids`, in the same order
+/// as `popdyn_get_positions`; returns the number of individuals.
+///
+/// # Safety
+///
+/// `simulation` must come from `popdyn_create` and not have been freed, and `ids` must
+/// be valid for `capacity` writes (or null with `capacity` 0).
+#[no_mangle]
+pub unsafe extern "C" fn popdyn_get_species(
+    simulation: *const Simulation,
+    ids: *mut u8,
+    capacity: usize,
+) -> usize {
+    let Some(simulation) = simulation.as_ref() else {
+        return 0;
+    };
+    let individuals = &simulation.population.individuals;
+    for (idx, individual) in individuals.iter().take(capacity).enumerate() {
+        *ids.add(idx) = individual.species.id;
+    }
+    individuals.len()
+}
+
+/// Release a simulation; null is ignored.
+///
+/// # Safety
+///
+/// `simulation` must be null or come from `popdyn_create`, and must not be used again.
+#[no_mangle]
+pub unsafe extern "C" fn popdyn_free(simulation: *mut Simulation) {
+    if !simulation.is_null() {
+        drop(Box::from_raw(simulation));
+    }
+}
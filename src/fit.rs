@@ -0,0 +1,152 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::analysis::replicates;
+use crate::{CapPolicy, Population, SimError, Species};
+
+// fitted parameters, in this order
+pub const PARAMETERS: [&str; 4] = ["B0", "D0", "B1", "D1"];
+
+pub struct Prior {
+    // independent uniform priors on B0, D0, B1, D1
+    pub lower: [f64; 4],
+    pub upper: [f64; 4],
+}
+
+pub struct AbcResult {
+    pub accepted: Vec<[f64; 4]>,
+    pub distances: Vec<f64>,
+    // posterior mean and sd of the accepted draws
+    pub estimate: [f64; 4],
+    pub sd: [f64; 4],
+    // largest accepted distance and the fraction of draws accepted
+    pub tolerance: f64,
+    pub acceptance_rate: f64,
+}
+
+fn with_parameters(base: &Species, theta: &[f64; 4]) -> Species {
+    let mut species = base.clone();
+    species.B0 = theta[0];
+    species.D0 = theta[1];
+    species.B1 = theta[2];
+    species.D1 = theta[3];
+    species
+}
+
+fn simulated_series(
+    species: &Species,
+    times: &[f64],
+    cap: usize,
+    seed: u64,
+) -> Result<Vec<f64>, SimError> {
+    // abundance at each observed time; a run that stops early, whether extinct, left
+    // without events, or cut off at the cap, holds its final abundance through the
+    // remaining times
+
+    let mut population = Population::from_seed(vec![species], seed)?;
+    population.output_times = Some(times.to_vec());
+    population.size_cap = Some((cap, CapPolicy::Stop));
+    let max_t = times.last().copied().unwrap_or(0.0);
+    population.simulate(max_t + f64::EPSILON)?;
+
+    let mut series = vec![0.0; times.len()];
+    for (value, checkpoint) in series.iter_mut().zip(&population.history.checkpoints) {
+        *value = checkpoint.counts.iter().sum::<usize>() as f64;
+    }
+    Ok(series)
+}
+
+pub fn abc(
+    base: &Species,
+    observed: &[(f64, usize)],
+    prior: &Prior,
+    draws: usize,
+    accept: usize,
+    seed: u64,
+) -> Result<AbcResult, SimError> {
+    // Rejection ABC: simulate the base species under `draws` parameter sets from the
+    // prior, starting from its initial pattern, and keep the `accept` draws whose
+    // abundance series lie closest to the observed (time, abundance) series, measured as
+    // the root mean square difference of log(1 + abundance); runs are cut off at three
+    // times the largest observed abundance to bound the cost of explosive draws, and
+    // draws giving an invalid species or rate are rejected outright. Observations may
+    // come in any order; each prior's bounds must be finite with lower <= upper
+
+    for (k, name) in PARAMETERS.iter().enumerate() {
+        let (lower, upper) = (prior.lower[k], prior.upper[k]);
+        if !(lower.is_finite() && upper.is_finite() && lower <= upper) {
+            return Err(SimError::Fit(format!(
+                "prior on {name} needs finite bounds with lower <= upper, got [{lower}, {upper}]"
+            )));
+        }
+    }
+    if let Some((t, _)) = observed.iter().find(|x| !(x.0.is_finite() && x.0 >= 0.0)) {
+        return Err(SimError::Fit(format!(
+            "observation time {t} must be finite and non-negative"
+        )));
+    }
+    let mut observed = observed.to_vec();
+    observed.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let mut rng = StdRng::seed_from_u64(seed);
+    let times: Vec<f64> = observed.iter().map(|x| x.0).collect();
+    let target: Vec<f64> = observed.iter().map(|x| (x.1 as f64).ln_1p()).collect();
+    let cap = 3 * observed.iter().map(|x| x.1).max().unwrap_or(0).max(10);
+
+    // every draw and its seed is taken up front so that the result does not depend on
+    // the order in which the parallel runs finish
+    let plan: Vec<([f64; 4], u64)> = (0..draws)
+        .map(|_| {
+            let mut theta = [0.0; 4];
+            for (k, value) in theta.iter_mut().enumerate() {
+                *value = rng.gen_range(prior.lower[k]..=prior.upper[k]);
+            }
+            (theta, rng.gen())
+        })
+        .collect();
+    let results = replicates(&plan, |(theta, seed)| {
+        let species = with_parameters(base, &theta);
+        let series = match simulated_series(&species, &times, cap, seed) {
+            Ok(series) => series,
+            Err(SimError::Species(_) | SimError::InvalidRate { .. }) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let distance = (series
+            .iter()
+            .zip(&target)
+            .map(|(s, o)| (s.ln_1p() - o).powi(2))
+            .sum::<f64>()
+            / target.len().max(1) as f64)
+            .sqrt();
+        Ok(Some((distance, theta)))
+    })?;
+    let mut scored: Vec<(f64, [f64; 4])> = results.into_iter().flatten().collect();
+    scored.sort_by(|a, b| a.0.total_cmp(&b.0));
+    scored.truncate(accept.max(1));
+    if scored.is_empty() {
+        return Err(SimError::Fit(format!(
+            "none of the {draws} draws gave a valid species and rates"
+        )));
+    }
+
+    let n = scored.len() as f64;
+    let mut estimate = [0.0; 4];
+    let mut sd = [0.0; 4];
+    for k in 0..4 {
+        estimate[k] = scored.iter().map(|x| x.1[k]).sum::<f64>() / n;
+        sd[k] = (scored
+            .iter()
+            .map(|x| (x.1[k] - estimate[k]).powi(2))
+            .sum::<f64>()
+            / n)
+            .sqrt();
+    }
+    Ok(AbcResult {
+        tolerance: scored.last().map_or(0.0, |x| x.0),
+        acceptance_rate: n / draws.max(1) as f64,
+        distances: scored.iter().map(|x| x.0).collect(),
+        accepted: scored.iter().map(|x| x.1).collect(),
+        estimate,
+        sd,
+    })
+}
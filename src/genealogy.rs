@@ -0,0 +1,77 @@
+#[derive(Clone)]
+pub struct Lineage {
+    pub uid: usize,
+    pub parent: Option<usize>,
+    pub species: u8,
+    pub birth_time: f64,
+    pub death_time: Option<f64>,
+}
+
+#[derive(Clone)]
+pub struct Genealogy {
+    // indexed by uid, which is handed out sequentially
+    pub lineages: Vec<Lineage>,
+}
+
+impl Genealogy {
+    pub fn new() -> Self {
+        Genealogy { lineages: vec![] }
+    }
+
+    pub fn record_birth(&mut self, parent: Option<usize>, species: u8, birth_time: f64) -> usize {
+        // Register a new individual and return its lifetime-unique id
+
+        let uid = self.lineages.len();
+        self.lineages.push(Lineage {
+            uid,
+            parent,
+            species,
+            birth_time,
+            death_time: None,
+        });
+        uid
+    }
+
+    pub fn record_death(&mut self, uid: usize, death_time: f64) {
+        self.lineages[uid].death_time = Some(death_time);
+    }
+
+    pub fn ancestors(&self, uid: usize) -> Vec<usize> {
+        // The chain of parents from uid back to its founder, starting with uid itself
+
+        let mut chain = vec![uid];
+        while let Some(parent) = self.lineages[*chain.last().unwrap()].parent {
+            chain.push(parent);
+        }
+        chain
+    }
+
+    pub fn most_recent_common_ancestor(&self, first: usize, second: usize) -> Option<usize> {
+        let first_line = self.ancestors(first);
+        self.ancestors(second)
+            .into_iter()
+            .find(|uid| first_line.contains(uid))
+    }
+
+    pub fn coalescence_time(&self, first: usize, second: usize) -> Option<f64> {
+        // Time at which the two lineages merge looking backwards, i.e. the earlier birth of
+        // the two children of the common ancestor that lead to them
+
+        let ancestor = self.most_recent_common_ancestor(first, second)?;
+        let split = |uid: usize| {
+            let line = self.ancestors(uid);
+            let position = line.iter().position(|x| *x == ancestor).unwrap();
+            match position {
+                0 => f64::INFINITY,
+                _ => self.lineages[line[position - 1]].birth_time,
+            }
+        };
+        Some(split(first).min(split(second)))
+    }
+}
+
+impl Default for Genealogy {
+    fn default() -> Self {
+        Genealogy::new()
+    }
+}
@@ -0,0 +1,106 @@
+use ndarray::Array2;
+use rand::Rng;
+
+use crate::history::Checkpoint;
+use crate::Species;
+
+pub fn random_genome(species: &Species, rng: &mut impl Rng) -> Vec<u8> {
+    // Draw each neutral locus uniformly from the species' allele range
+
+    (0..species.Nloci)
+        .map(|_| rng.gen_range(0..species.Nalleles.max(1)))
+        .collect()
+}
+
+pub fn inherit(genome: &[u8], species: &Species, rng: &mut impl Rng) -> Vec<u8> {
+    // Copy a parent genome, replacing each locus by a random allele with probability Nmut
+
+    genome
+        .iter()
+        .map(|allele| {
+            if rng.gen::<f64>() < species.Nmut {
+                rng.gen_range(0..species.Nalleles.max(1))
+            } else {
+                *allele
+            }
+        })
+        .collect()
+}
+
+fn gene_diversity(genomes: &[&Vec<u8>]) -> f64 {
+    // Expected heterozygosity 1 - sum(p^2), averaged over loci
+
+    let loci = genomes.iter().map(|g| g.len()).min().unwrap_or(0);
+    if loci == 0 {
+        return 0.0;
+    }
+    let n = genomes.len() as f64;
+    let mut total = 0.0;
+    for locus in 0..loci {
+        let mut counts = [0usize; 256];
+        for genome in genomes {
+            counts[genome[locus] as usize] += 1;
+        }
+        total += 1.0 - counts.iter().map(|c| (*c as f64 / n).powi(2)).sum::<f64>();
+    }
+    total / loci as f64
+}
+
+pub fn heterozygosity(checkpoint: &Checkpoint, species: Option<u8>) -> f64 {
+    // Expected heterozygosity of one species (or everyone) at a checkpoint
+
+    let genomes: Vec<&Vec<u8>> = checkpoint
+        .genomes
+        .iter()
+        .zip(checkpoint.species.iter())
+        .filter(|(_, id)| species.is_none_or(|s| s == **id))
+        .map(|(g, _)| g)
+        .collect();
+    gene_diversity(&genomes)
+}
+
+pub fn fst(checkpoint: &Checkpoint, first: &[usize], second: &[usize]) -> f64 {
+    // Nei's Fst (H_T - H_S) / H_T between two groups of individuals at a checkpoint
+
+    if first.is_empty() || second.is_empty() {
+        return 0.0;
+    }
+    let a: Vec<&Vec<u8>> = first.iter().map(|i| &checkpoint.genomes[*i]).collect();
+    let b: Vec<&Vec<u8>> = second.iter().map(|i| &checkpoint.genomes[*i]).collect();
+    let pooled: Vec<&Vec<u8>> = a.iter().chain(b.iter()).copied().collect();
+
+    let h_s = (gene_diversity(&a) + gene_diversity(&b)) / 2.0;
+    let h_t = gene_diversity(&pooled);
+    if h_t > 0.0 {
+        (h_t - h_s) / h_t
+    } else {
+        0.0
+    }
+}
+
+pub fn deme_fst(checkpoint: &Checkpoint, resolution: usize) -> Array2<f64> {
+    // Pairwise Fst between the cells of a resolution x resolution grid of demes, indexed
+    // row-major by cell, for isolation-by-distance analyses; empty for a resolution of 0
+
+    if resolution == 0 {
+        return Array2::zeros((0, 0));
+    }
+    let cells = resolution * resolution;
+    let mut demes: Vec<Vec<usize>> = vec![vec![]; cells];
+    for idx in 0..checkpoint.coords.len() {
+        let (x, y) = checkpoint.coords.get(idx);
+        let row = ((y * resolution as f64) as usize).min(resolution - 1);
+        let col = ((x * resolution as f64) as usize).min(resolution - 1);
+        demes[row * resolution + col].push(idx);
+    }
+
+    let mut matrix = Array2::<f64>::zeros((cells, cells));
+    for i in 0..cells {
+        for j in (i + 1)..cells {
+            let value = fst(checkpoint, &demes[i], &demes[j]);
+            matrix[[i, j]] = value;
+            matrix[[j, i]] = value;
+        }
+    }
+    matrix
+}
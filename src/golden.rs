@@ -0,0 +1,167 @@
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::ndjson::event_json;
+use crate::{Population, SimError, Species};
+
+// Small seeded scenarios whose event logs are checked in under golden/, so that changes
+// to the rate and bookkeeping code can be shown not to alter behaviour. Logs are compared
+// as text, one event_json line per event; floating-point results can differ between
+// platforms, so the files are only authoritative on the platform that wrote them.
+// tests/golden.rs runs the check; BLESS=1 cargo test --test golden rewrites the logs.
+pub struct GoldenCase {
+    pub name: &'static str,
+    pub species: Vec<Species>,
+    pub seed: u64,
+    pub max_t: f64,
+}
+
+#[derive(Debug)]
+pub struct GoldenMismatch {
+    pub case: &'static str,
+    // 1-based line of the first difference
+    pub line: usize,
+    pub expected: Option<String>,
+    pub actual: Option<String>,
+}
+
+impl fmt::Display for GoldenMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "golden case {} differs at line {}: expected {:?}, got {:?}",
+            self.case, self.line, self.expected, self.actual
+        )
+    }
+}
+
+impl error::Error for GoldenMismatch {}
+
+pub fn golden_cases() -> Vec<GoldenCase> {
+    let logistic = Species {
+        B0: 0.6,
+        D0: 0.2,
+        D1: 0.01,
+        Wdrmax: 0.1,
+        Wdsd: 0.05,
+        Mbsd: 0.05,
+        C1: 30.0,
+        ..Default::default()
+    };
+    let mover = Species {
+        B0: 0.4,
+        D0: 0.2,
+        D1: 0.01,
+        Wdrmax: 0.1,
+        Wdsd: 0.05,
+        Mbsd: 0.05,
+        Mintegral: 0.5,
+        Mdsd: 0.02,
+        Mrmax: 0.1,
+        Msd: 0.05,
+        M1: 0.05,
+        C1: 20.0,
+        ..Default::default()
+    };
+    let host = Species {
+        id: 1,
+        B0: 0.3,
+        D0: 0.2,
+        I0: 0.5,
+        Eprev: 0.3,
+        Ebeta: 0.02,
+        Ermax: 0.2,
+        Esd: 0.1,
+        Egamma: 0.3,
+        Ealpha: 0.1,
+        Mbsd: 0.1,
+        C1: 15.0,
+        ..Default::default()
+    };
+    let staged = Species {
+        B0: 0.5,
+        D0: 0.1,
+        JB0: 0.0,
+        JD0: 0.2,
+        G0: 0.5,
+        Clutch: 2.0,
+        Lmax: 4.0,
+        Rexcl: 0.01,
+        Mbsd: 0.05,
+        C1: 20.0,
+        ..Default::default()
+    };
+    vec![
+        GoldenCase {
+            name: "logistic",
+            species: vec![logistic],
+            seed: 1,
+            max_t: 5.0,
+        },
+        GoldenCase {
+            name: "movement_and_disease",
+            species: vec![mover, host],
+            seed: 2,
+            max_t: 3.0,
+        },
+        GoldenCase {
+            name: "stages",
+            species: vec![staged],
+            seed: 3,
+            max_t: 4.0,
+        },
+    ]
+}
+
+pub fn event_log(case: &GoldenCase) -> Result<Vec<String>, SimError> {
+    let mut population = Population::from_seed(case.species.iter().collect(), case.seed)?;
+    let mut lines = vec![];
+    while let Some(record) = population.step_within(case.max_t)? {
+        lines.push(event_json(&record));
+    }
+    Ok(lines)
+}
+
+pub fn check_golden(dir: impl AsRef<Path>, bless: bool) -> Result<usize, SimError> {
+    // Rerun every golden case and compare its event log with <dir>/<name>.ndjson,
+    // returning the number of cases checked; with bless the current log is written
+    // instead, and without it a missing file is an error rather than a pass
+
+    let dir = dir.as_ref();
+    let cases = golden_cases();
+    for case in &cases {
+        let actual = event_log(case)?;
+        let path = dir.join(format!("{}.ndjson", case.name));
+        if bless {
+            fs::create_dir_all(dir)?;
+            fs::write(&path, actual.join("\n") + "\n")?;
+            continue;
+        }
+        if !path.exists() {
+            let message = format!(
+                "golden file {} is missing; rerun with BLESS=1 to write it",
+                path.display()
+            );
+            return Err(io::Error::new(io::ErrorKind::NotFound, message).into());
+        }
+
+        let text = fs::read_to_string(&path)?;
+        let expected: Vec<&str> = text.lines().collect();
+        let length = expected.len().max(actual.len());
+        if let Some(line) =
+            (0..length).find(|k| expected.get(*k).copied() != actual.get(*k).map(|x| x.as_str()))
+        {
+            return Err(GoldenMismatch {
+                case: case.name,
+                line: line + 1,
+                expected: expected.get(line).map(|x| x.to_string()),
+                actual: actual.get(line).cloned(),
+            }
+            .into());
+        }
+    }
+    Ok(cases.len())
+}
@@ -0,0 +1,103 @@
+// most cells along each side, bounding the grid's memory when every radius is tiny
+const MAX_SIDE: usize = 128;
+
+// Individuals bucketed by position into a side x side grid of cells over the unit torus,
+// each cell at least as wide as the largest interaction radius, so that everyone within
+// that radius of a point is in the point's cell or one of the eight around it. Cells
+// hold indices into the population's individuals and are kept in step with them as
+// individuals arrive, leave, and move.
+#[derive(Clone, Default)]
+pub struct Grid {
+    side: usize,
+    radius: f64,
+    cells: Vec<Vec<usize>>,
+}
+
+impl Grid {
+    pub fn new(radius: f64, positions: impl IntoIterator<Item = (f64, f64)>) -> Self {
+        let side = if radius > 0.0 {
+            ((1.0 / radius).floor() as usize).clamp(1, MAX_SIDE)
+        } else {
+            MAX_SIDE
+        };
+        let mut grid = Grid {
+            side,
+            radius,
+            cells: vec![vec![]; side * side],
+        };
+        for (idx, (x, y)) in positions.into_iter().enumerate() {
+            grid.insert(idx, x, y);
+        }
+        grid
+    }
+
+    pub fn radius(&self) -> f64 {
+        // how far a neighbour query is guaranteed to reach
+        self.radius
+    }
+
+    fn cell(&self, x: f64, y: f64) -> (usize, usize) {
+        let side = self.side as f64;
+        (
+            ((y * side) as usize).min(self.side - 1),
+            ((x * side) as usize).min(self.side - 1),
+        )
+    }
+
+    fn index(&self, (row, col): (usize, usize)) -> usize {
+        row * self.side + col
+    }
+
+    pub fn insert(&mut self, idx: usize, x: f64, y: f64) {
+        let cell = self.index(self.cell(x, y));
+        self.cells[cell].push(idx);
+    }
+
+    pub fn remove(&mut self, idx: usize, x: f64, y: f64) {
+        let cell = self.index(self.cell(x, y));
+        let cell = &mut self.cells[cell];
+        let position = cell
+            .iter()
+            .position(|x| *x == idx)
+            .expect("listed in its cell");
+        cell.swap_remove(position);
+    }
+
+    pub fn relabel(&mut self, from: usize, to: usize, x: f64, y: f64) {
+        // follow an individual whose index changed, as when it is swapped into the place
+        // of one that died
+        let cell = self.index(self.cell(x, y));
+        for entry in self.cells[cell].iter_mut().filter(|x| **x == from) {
+            *entry = to;
+        }
+    }
+
+    pub fn contains(&self, idx: usize, x: f64, y: f64) -> bool {
+        self.cells[self.index(self.cell(x, y))].contains(&idx)
+    }
+
+    pub fn len(&self) -> usize {
+        self.cells.iter().map(Vec::len).sum()
+    }
+
+    pub fn near(&self, x: f64, y: f64) -> Vec<usize> {
+        // Everyone in the cell of a point and the cells around it, in index order so that
+        // sums over them do not depend on the order of the cells' contents; a superset
+        // of everyone within the radius
+
+        let mut found = vec![];
+        if self.side < 3 {
+            self.cells.iter().for_each(|x| found.extend(x));
+        } else {
+            let (row, col) = self.cell(x, y);
+            for d_row in [self.side - 1, 0, 1] {
+                for d_col in [self.side - 1, 0, 1] {
+                    let cell = ((row + d_row) % self.side, (col + d_col) % self.side);
+                    found.extend(&self.cells[self.index(cell)]);
+                }
+            }
+        }
+        found.sort_unstable();
+        found
+    }
+}
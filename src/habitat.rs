@@ -0,0 +1,75 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use ndarray::Array2;
+
+#[derive(Clone, Debug)]
+pub struct Habitat {
+    quality: Array2<f64>,
+}
+
+impl Habitat {
+    pub fn new(quality: Array2<f64>) -> Self {
+        Habitat { quality }
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        // Read a raster of whitespace-separated values, one grid row per line, with the
+        // first line covering y in [0, 1 / rows); every row must have the same number of
+        // values and every value must be finite and non-negative
+
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+        let text = fs::read_to_string(path)?;
+        let mut values = vec![];
+        let mut rows = 0;
+        let mut cols = None;
+        for (number, line) in text.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let start = values.len();
+            for value in line.split_whitespace() {
+                let quality = value
+                    .parse::<f64>()
+                    .map_err(|e| invalid(format!("line {}: {value:?}: {e}", number + 1)))?;
+                if !quality.is_finite() || quality < 0.0 {
+                    return Err(invalid(format!(
+                        "line {}: quality {value} must be finite and non-negative",
+                        number + 1
+                    )));
+                }
+                values.push(quality);
+            }
+            let len = values.len() - start;
+            if *cols.get_or_insert(len) != len {
+                return Err(invalid(format!(
+                    "line {}: {len} values where the first row has {}",
+                    number + 1,
+                    cols.unwrap()
+                )));
+            }
+            rows += 1;
+        }
+        if rows == 0 {
+            return Err(invalid("habitat raster has no rows".into()));
+        }
+
+        let quality = Array2::from_shape_vec((rows, cols.unwrap()), values)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        Ok(Habitat::new(quality))
+    }
+
+    pub fn quality(&self) -> &Array2<f64> {
+        &self.quality
+    }
+
+    pub fn at(&self, x_coord: f64, y_coord: f64) -> f64 {
+        // Look up the value of the cell containing a point of the unit square
+
+        let (rows, cols) = self.quality.dim();
+        let row = ((y_coord * rows as f64) as usize).min(rows - 1);
+        let col = ((x_coord * cols as f64) as usize).min(cols - 1);
+        self.quality[[row, col]]
+    }
+}
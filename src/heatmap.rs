@@ -0,0 +1,156 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use ndarray::{Array2, Array3};
+
+use crate::history::Checkpoint;
+use crate::individual::torus_offset;
+
+pub fn density_grid(
+    checkpoint: &Checkpoint,
+    resolution: usize,
+    species: Option<u8>,
+) -> Array2<f64> {
+    // Bin a checkpoint's individuals (optionally of one species) into a resolution x
+    // resolution grid over the unit square, as densities per unit area; a zero
+    // resolution gives an empty grid
+
+    let mut grid = Array2::<f64>::zeros((resolution, resolution));
+    if resolution == 0 {
+        return grid;
+    }
+    let cell_area = 1.0 / (resolution * resolution) as f64;
+    for (idx, id) in checkpoint.species.iter().enumerate() {
+        if species.is_some_and(|s| s != *id) {
+            continue;
+        }
+        let (x, y) = checkpoint.coords.get(idx);
+        let row = ((y * resolution as f64) as usize).min(resolution - 1);
+        let col = ((x * resolution as f64) as usize).min(resolution - 1);
+        grid[[row, col]] += 1.0 / cell_area;
+    }
+    grid
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Kde {
+    pub resolution: usize,
+    // sd of the gaussian kernel; zero falls back to plain cell counts
+    pub bandwidth: f64,
+    pub species: Option<u8>,
+}
+
+pub fn kernel_density(checkpoint: &Checkpoint, kde: &Kde) -> Array2<f64> {
+    // Gaussian kernel density estimate evaluated at the cell centres of a resolution x
+    // resolution grid, wrapping around the torus and truncated at three bandwidths; each
+    // kernel is renormalised over the cells it covers so that, like density_grid, the
+    // estimate integrates to the number of individuals
+
+    let resolution = kde.resolution;
+    let h = kde.bandwidth;
+    if h <= 0.0 || resolution == 0 {
+        return density_grid(checkpoint, resolution, kde.species);
+    }
+
+    let mut grid = Array2::<f64>::zeros((resolution, resolution));
+    let width = 1.0 / resolution as f64;
+    let reach = ((3.0 * h / width).ceil() as usize).min(resolution / 2);
+    let span = (2 * reach + 1).min(resolution);
+    for (idx, id) in checkpoint.species.iter().enumerate() {
+        if kde.species.is_some_and(|s| s != *id) {
+            continue;
+        }
+        let (x, y) = checkpoint.coords.get(idx);
+        let row = ((y * resolution as f64) as usize).min(resolution - 1);
+        let col = ((x * resolution as f64) as usize).min(resolution - 1);
+        let mut weights = vec![];
+        for i in (row + resolution - reach)..(row + resolution - reach + span) {
+            for j in (col + resolution - reach)..(col + resolution - reach + span) {
+                let (r, c) = (i % resolution, j % resolution);
+                let dy = torus_offset(y, (r as f64 + 0.5) * width);
+                let dx = torus_offset(x, (c as f64 + 0.5) * width);
+                weights.push((
+                    (r, c),
+                    (-(dx.powi(2) + dy.powi(2)) / (2.0 * h.powi(2))).exp(),
+                ));
+            }
+        }
+        let total: f64 = weights.iter().map(|(_, w)| w).sum();
+        for (cell, weight) in weights {
+            grid[cell] += weight / (total * width.powi(2));
+        }
+    }
+    grid
+}
+
+pub fn density_difference(
+    before: &Checkpoint,
+    after: &Checkpoint,
+    resolution: usize,
+    species: Option<u8>,
+) -> Array2<f64> {
+    // Change in density from `before` to `after`, positive where the species gained
+    // density; the checkpoints may come from the same run or from two different runs
+
+    density_grid(after, resolution, species) - density_grid(before, resolution, species)
+}
+
+pub(crate) fn hue_to_rgb(hue: f64) -> [f64; 3] {
+    // Fully saturated, full-value colour for a hue in [0, 1)
+
+    let h = hue.rem_euclid(1.0) * 6.0;
+    let x = 1.0 - (h % 2.0 - 1.0).abs();
+    match h as usize {
+        0 => [1.0, x, 0.0],
+        1 => [x, 1.0, 0.0],
+        2 => [0.0, 1.0, x],
+        3 => [0.0, x, 1.0],
+        4 => [x, 0.0, 1.0],
+        _ => [1.0, 0.0, x],
+    }
+}
+
+pub fn composite(checkpoint: &Checkpoint, resolution: usize, species: &[u8]) -> Array3<u8> {
+    // Overlay several species' density grids as one RGB image, each in its species'
+    // display colour and scaled by its own maximum
+
+    let mut image = Array3::<f64>::zeros((resolution, resolution, 3));
+    for (k, id) in species.iter().enumerate() {
+        let grid = density_grid(checkpoint, resolution, Some(*id));
+        let max = grid.fold(0.0_f64, |a, b| a.max(*b));
+        if max == 0.0 {
+            continue;
+        }
+        let colour = checkpoint
+            .colors
+            .get(*id as usize)
+            .map_or(hue_to_rgb(k as f64 / species.len() as f64), |x| {
+                x.map(|c| c as f64 / 255.0)
+            });
+        for ((row, col), value) in grid.indexed_iter() {
+            for channel in 0..3 {
+                image[[row, col, channel]] += colour[channel] * value / max;
+            }
+        }
+    }
+    image.mapv(|x| (x.min(1.0) * 255.0).round() as u8)
+}
+
+pub fn write_ppm(image: &Array3<u8>, path: impl AsRef<Path>) -> io::Result<()> {
+    // Save an RGB image as a binary PPM, flipping rows so that y increases upwards
+
+    let (rows, cols, _) = image.dim();
+    let mut file = BufWriter::new(File::create(path)?);
+    write!(file, "P6\n{cols} {rows}\n255\n")?;
+    for row in (0..rows).rev() {
+        for col in 0..cols {
+            file.write_all(&[
+                image[[row, col, 0]],
+                image[[row, col, 1]],
+                image[[row, col, 2]],
+            ])?;
+        }
+    }
+    file.flush()
+}
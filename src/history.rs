@@ -0,0 +1,542 @@
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use ndarray::Array2;
+
+use crate::analysis::torus_distance;
+
+use crate::heatmap::{self, Kde};
+use crate::species::palette;
+use crate::Population;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Precision {
+    Single,
+    Double,
+}
+
+#[derive(Clone)]
+pub enum Coordinates {
+    Single(Vec<[f32; 2]>),
+    Double(Vec<[f64; 2]>),
+}
+
+impl Coordinates {
+    pub fn len(&self) -> usize {
+        match self {
+            Coordinates::Single(coords) => coords.len(),
+            Coordinates::Double(coords) => coords.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, idx: usize) -> (f64, f64) {
+        // Position of the idx-th individual, widened back to f64 if stored as f32
+
+        match self {
+            Coordinates::Single(coords) => (coords[idx][0] as f64, coords[idx][1] as f64),
+            Coordinates::Double(coords) => (coords[idx][0], coords[idx][1]),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Checkpoint {
+    pub t: f64,
+    pub uids: Vec<usize>,
+    pub species: Vec<u8>,
+    pub coords: Coordinates,
+    pub traits: Vec<f64>,
+    pub genomes: Vec<Vec<u8>>,
+    // number of individuals, label and display colour, each indexed by species id
+    pub counts: Vec<usize>,
+    pub names: Vec<String>,
+    pub colors: Vec<[u8; 3]>,
+    pub heatmap: Option<Array2<f64>>,
+    // length of the event log when the checkpoint was taken, where replaying the log
+    // from the checkpoint starts; changes logged at the checkpoint's own time may fall
+    // on either side of it
+    pub logged: usize,
+}
+
+#[derive(Clone)]
+pub struct HarvestRecord {
+    pub t: f64,
+    pub species: u8,
+    pub removed: usize,
+}
+
+#[derive(Clone)]
+pub enum Change {
+    Added { species: u8, x: f64, y: f64 },
+    Removed,
+    Moved { x: f64, y: f64 },
+}
+
+#[derive(Clone)]
+pub struct LogEntry {
+    pub t: f64,
+    pub uid: usize,
+    pub change: Change,
+}
+
+#[derive(Clone)]
+pub struct Configuration {
+    pub t: f64,
+    pub uids: Vec<usize>,
+    pub species: Vec<u8>,
+    pub coords: Vec<(f64, f64)>,
+}
+
+impl From<&Checkpoint> for Configuration {
+    fn from(checkpoint: &Checkpoint) -> Self {
+        Configuration {
+            t: checkpoint.t,
+            uids: checkpoint.uids.clone(),
+            species: checkpoint.species.clone(),
+            coords: (0..checkpoint.coords.len())
+                .map(|i| checkpoint.coords.get(i))
+                .collect(),
+        }
+    }
+}
+
+impl Configuration {
+    fn apply(&mut self, entry: &LogEntry) {
+        // replay one logged change, moving the configuration on to its time
+        let position = self.uids.iter().position(|x| *x == entry.uid);
+        match (&entry.change, position) {
+            (Change::Added { species, x, y }, _) => {
+                self.uids.push(entry.uid);
+                self.species.push(*species);
+                self.coords.push((*x, *y));
+            }
+            // the last individual takes the place of a removed one, as in the population
+            (Change::Removed, Some(idx)) => {
+                self.uids.swap_remove(idx);
+                self.species.swap_remove(idx);
+                self.coords.swap_remove(idx);
+            }
+            (Change::Moved { x, y }, Some(idx)) => self.coords[idx] = (*x, *y),
+            _ => (),
+        }
+        self.t = entry.t;
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct Stationarity {
+    // length of each of the two trailing windows compared
+    pub window: f64,
+    // largest accepted shift in mean abundance, in pooled within-window sds
+    pub tolerance: f64,
+}
+
+pub struct TimeSeries {
+    pub t: Vec<f64>,
+    // abundance[species id][checkpoint]
+    pub abundance: Vec<Vec<usize>>,
+    pub births: Vec<usize>,
+    pub deaths: Vec<usize>,
+    // mean pairwise distance between all individuals
+    pub mean_distance: Vec<f64>,
+}
+
+#[derive(Clone)]
+pub struct History {
+    pub precision: Precision,
+    pub checkpoints: Vec<Checkpoint>,
+    pub harvests: Vec<HarvestRecord>,
+    // when enabled, only the first checkpoint is stored and later configurations are
+    // rebuilt from the log of changes
+    pub events: Option<Vec<LogEntry>>,
+    // density estimate to attach to each checkpoint
+    pub heatmap: Option<Kde>,
+}
+
+pub(crate) fn csv_field(text: &str) -> String {
+    // quote text that would otherwise split or break a CSV row
+    if text.contains([',', '"']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+impl History {
+    pub fn new(precision: Precision) -> Self {
+        History {
+            precision,
+            checkpoints: vec![],
+            harvests: vec![],
+            events: None,
+            heatmap: None,
+        }
+    }
+
+    pub fn time_series(&self) -> TimeSeries {
+        // Per-checkpoint summaries aligned on the checkpoint times, or with an event log
+        // on the first checkpoint and every logged time after it. Births and deaths are
+        // cumulative counts of uids appearing and disappearing between those times, so
+        // with checkpoints individuals living entirely between two are not counted
+
+        let configurations: Vec<Configuration> = match (&self.events, self.checkpoints.first()) {
+            (Some(events), Some(first)) => {
+                let mut configuration = Configuration::from(first);
+                let mut configurations = vec![configuration.clone()];
+                let mut entries = events[first.logged..].iter().peekable();
+                while let Some(entry) = entries.next() {
+                    configuration.apply(entry);
+                    if entries.peek().is_none_or(|next| next.t > entry.t) {
+                        configurations.push(configuration.clone());
+                    }
+                }
+                configurations
+            }
+            _ => self.checkpoints.iter().map(Configuration::from).collect(),
+        };
+
+        let species = configurations
+            .iter()
+            .flat_map(|x| x.species.iter().map(|id| *id as usize + 1))
+            .chain(self.checkpoints.iter().map(|x| x.counts.len()))
+            .max()
+            .unwrap_or(0);
+        let mut series = TimeSeries {
+            t: vec![],
+            abundance: vec![vec![]; species],
+            births: vec![],
+            deaths: vec![],
+            mean_distance: vec![],
+        };
+        let (mut births, mut deaths) = (0, 0);
+        let mut previous: Option<HashSet<usize>> = None;
+        for configuration in &configurations {
+            series.t.push(configuration.t);
+            let mut counts = vec![0; species];
+            for id in &configuration.species {
+                counts[*id as usize] += 1;
+            }
+            for (abundance, count) in series.abundance.iter_mut().zip(counts) {
+                abundance.push(count);
+            }
+
+            let current: HashSet<usize> = configuration.uids.iter().copied().collect();
+            if let Some(previous) = &previous {
+                births += current.difference(previous).count();
+                deaths += previous.difference(&current).count();
+            }
+            series.births.push(births);
+            series.deaths.push(deaths);
+            previous = Some(current);
+
+            let coords = &configuration.coords;
+            let n = coords.len();
+            let mut total = 0.0;
+            for i in 0..n {
+                for j in (i + 1)..n {
+                    total += torus_distance(coords[i], coords[j]);
+                }
+            }
+            let pairs = (n * n.saturating_sub(1) / 2).max(1) as f64;
+            series.mean_distance.push(total / pairs);
+        }
+        series
+    }
+
+    pub fn stationary_at(&self, detector: &Stationarity, t: f64) -> bool {
+        // Compare every species' mean abundance over the checkpoints in [t - window, t]
+        // with the window before it; the system counts as stationary when no mean has
+        // shifted by more than tolerance pooled standard deviations
+
+        let window = detector.window;
+        if self
+            .checkpoints
+            .first()
+            .is_none_or(|x| x.t > t - 2.0 * window)
+        {
+            return false;
+        }
+        let index = |time: f64| self.checkpoints.partition_point(|x| x.t < time);
+        let (start, middle, end) = (
+            index(t - 2.0 * window),
+            index(t - window),
+            index(t + f64::EPSILON),
+        );
+        let (earlier, later) = (
+            &self.checkpoints[start..middle],
+            &self.checkpoints[middle..end],
+        );
+        if earlier.len() < 2 || later.len() < 2 {
+            return false;
+        }
+
+        let species = self
+            .checkpoints
+            .iter()
+            .map(|x| x.counts.len())
+            .max()
+            .unwrap_or(0);
+        let moments = |checkpoints: &[Checkpoint], id: usize| {
+            let counts: Vec<f64> = checkpoints
+                .iter()
+                .map(|x| x.counts.get(id).copied().unwrap_or(0) as f64)
+                .collect();
+            let mean = counts.iter().sum::<f64>() / counts.len() as f64;
+            let variance =
+                counts.iter().map(|c| (c - mean).powi(2)).sum::<f64>() / (counts.len() - 1) as f64;
+            (mean, variance)
+        };
+        (0..species).all(|id| {
+            let (first_mean, first_variance) = moments(earlier, id);
+            let (second_mean, second_variance) = moments(later, id);
+            let pooled = ((first_variance + second_variance) / 2.0).sqrt();
+            (second_mean - first_mean).abs() <= detector.tolerance * pooled
+        })
+    }
+
+    pub fn stationary_since(&self, detector: &Stationarity) -> Option<f64> {
+        // Earliest checkpoint time at which the detector first reports stationarity
+
+        self.checkpoints
+            .iter()
+            .map(|x| x.t)
+            .find(|t| self.stationary_at(detector, *t))
+    }
+
+    pub fn to_csv(&self, mut writer: impl Write) -> io::Result<()> {
+        // Long format, one row per individual per checkpoint
+
+        writeln!(writer, "t,species,index,uid,x,y,name")?;
+        for checkpoint in &self.checkpoints {
+            for (idx, (uid, species)) in checkpoint.uids.iter().zip(&checkpoint.species).enumerate()
+            {
+                let (x, y) = checkpoint.coords.get(idx);
+                let name = csv_field(checkpoint.names.get(*species as usize).map_or("", |x| x));
+                writeln!(
+                    writer,
+                    "{},{species},{idx},{uid},{x},{y},{name}",
+                    checkpoint.t
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn heatmap_to_csv(&self, mut writer: impl Write) -> io::Result<()> {
+        // Long format, one row per grid cell of each checkpoint's density estimate, row 0
+        // covering the lowest y
+
+        writeln!(writer, "t,row,col,density")?;
+        for checkpoint in &self.checkpoints {
+            let Some(grid) = &checkpoint.heatmap else {
+                continue;
+            };
+            for ((row, col), density) in grid.indexed_iter() {
+                writeln!(writer, "{},{row},{col},{density}", checkpoint.t)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn with_event_log(precision: Precision) -> Self {
+        History {
+            events: Some(vec![]),
+            ..History::new(precision)
+        }
+    }
+
+    pub fn reconstruct_at(&self, t: f64) -> Option<Configuration> {
+        // Start from the latest checkpoint at or before t and replay the logged changes
+        // up to t; None if t precedes the first checkpoint
+
+        let base = self.checkpoints.iter().rev().find(|x| x.t <= t)?;
+        let mut configuration = Configuration::from(base);
+        let events = self.events.as_deref().unwrap_or_default();
+        for entry in events[base.logged..].iter().filter(|x| x.t <= t) {
+            configuration.apply(entry);
+        }
+        configuration.t = t;
+        Some(configuration)
+    }
+}
+
+impl<'a> Population<'a> {
+    pub fn get_checkpoint(&self) -> Checkpoint {
+        // Snapshot the current positions at the precision configured for the history
+
+        let coords = match self.history.precision {
+            Precision::Single => Coordinates::Single(
+                self.individuals
+                    .iter()
+                    .map(|x| [x.x_coord as f32, x.y_coord as f32])
+                    .collect(),
+            ),
+            Precision::Double => Coordinates::Double(
+                self.individuals
+                    .iter()
+                    .map(|x| [x.x_coord, x.y_coord])
+                    .collect(),
+            ),
+        };
+        let ids = self
+            .species
+            .iter()
+            .map(|x| x.id as usize + 1)
+            .max()
+            .unwrap_or(0);
+        let mut counts = vec![0; ids];
+        for individual in &self.individuals {
+            counts[individual.species.id as usize] += 1;
+        }
+        let mut names: Vec<String> = (0..ids).map(|id| format!("species {id}")).collect();
+        let mut colors: Vec<[u8; 3]> = (0..ids).map(|id| palette(id, ids)).collect();
+        for species in &self.species {
+            names[species.id as usize] = species.label();
+            colors[species.id as usize] = species.display_color(ids);
+        }
+
+        let mut checkpoint = Checkpoint {
+            t: self.t,
+            uids: self.individuals.iter().map(|x| x.uid).collect(),
+            species: self.individuals.iter().map(|x| x.species.id).collect(),
+            coords,
+            traits: self.individuals.iter().map(|x| x.trait_value).collect(),
+            genomes: self.individuals.iter().map(|x| x.genome.clone()).collect(),
+            counts,
+            names,
+            colors,
+            heatmap: None,
+            logged: self.history.events.as_ref().map_or(0, Vec::len),
+        };
+        checkpoint.heatmap = self
+            .history
+            .heatmap
+            .map(|kde| heatmap::kernel_density(&checkpoint, &kde));
+        checkpoint
+    }
+
+    pub fn record_checkpoint(&mut self) {
+        // with fixed output times, checkpoints are taken by record_output_times instead
+        if self.output_times.is_some() {
+            return;
+        }
+        // nothing is kept until the burn-in period has elapsed
+        if self.t < self.burn_in {
+            return;
+        }
+        if let Some(last) = self.history.checkpoints.last() {
+            if self.history.events.is_some() || self.t - last.t < self.checkpoint_interval {
+                return;
+            }
+        }
+        let checkpoint = self.get_checkpoint();
+        self.history.checkpoints.push(checkpoint);
+    }
+
+    pub fn write_csv(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        // Save the history as CSV, with the run metadata in a sidecar file
+
+        let mut file = BufWriter::new(File::create(&path)?);
+        self.history.to_csv(&mut file)?;
+        file.flush()?;
+        self.metadata().write_sidecar(path)
+    }
+
+    pub(crate) fn record_output_times(&mut self, until: f64) {
+        // checkpoint every pending output time in [t, until), during which the state is
+        // frozen at what it is now
+
+        let Some(times) = self.output_times.as_ref() else {
+            return;
+        };
+        let due = times.partition_point(|x| *x < until);
+        self.record_due_output_times(due);
+    }
+
+    pub(crate) fn fill_output_times(&mut self, max_t: f64) {
+        // once a run has stopped early its state holds through to max_t, so checkpoint
+        // every output time left up to and including it
+
+        let Some(times) = self.output_times.as_ref() else {
+            return;
+        };
+        let due = times.partition_point(|x| *x <= max_t);
+        self.record_due_output_times(due);
+    }
+
+    fn record_due_output_times(&mut self, due: usize) {
+        // checkpoint the first due pending output times at the current state, apart from
+        // any before t or before the burn-in period has elapsed, and drop them all
+
+        let Some(times) = self.output_times.as_ref() else {
+            return;
+        };
+        let stamps: Vec<f64> = times[..due]
+            .iter()
+            .filter(|x| **x >= self.t && **x >= self.burn_in)
+            .copied()
+            .collect();
+        for t in stamps {
+            let mut checkpoint = self.get_checkpoint();
+            checkpoint.t = t;
+            self.history.checkpoints.push(checkpoint);
+        }
+        if let Some(times) = self.output_times.as_mut() {
+            times.drain(..due);
+        }
+    }
+
+    pub(crate) fn log_change(&mut self, uid: usize, change: Change) {
+        if let Some(events) = self.history.events.as_mut() {
+            events.push(LogEntry {
+                t: self.t,
+                uid,
+                change,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::schedule::Introduction;
+    use crate::{Population, SimulationConfig, Species};
+
+    #[test]
+    fn changes_at_the_first_checkpoint_time_are_replayed() {
+        // founders introduced at t = 0 are logged at the time of the first checkpoint,
+        // which was taken before they arrived
+        let species = Species {
+            B0: 0.5,
+            D0: 0.4,
+            ..Default::default()
+        };
+        let mut config = SimulationConfig::new(vec![&species], 2.0);
+        config.seed = Some(5);
+        config.event_log = true;
+        config.introductions.push(Introduction {
+            t: 0.0,
+            species: &species,
+            founders: 10,
+            location: (0.5, 0.5),
+            spread: 0.1,
+        });
+        let mut population = Population::from_config(&config).unwrap();
+        population.simulate(config.max_t).unwrap();
+        let history = population.history();
+        assert!(history.checkpoints[0].uids.is_empty());
+
+        assert_eq!(history.reconstruct_at(0.0).unwrap().uids.len(), 10);
+        let end = history.reconstruct_at(config.max_t).unwrap();
+        assert_eq!(end.uids.len(), population.size());
+        let series = history.time_series();
+        assert_eq!(series.abundance[0][1], 10);
+        assert_eq!(*series.abundance[0].last().unwrap(), population.size());
+    }
+}
@@ -0,0 +1,231 @@
+use crate::{kernels, Event, Species};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Health {
+    Susceptible,
+    Infected,
+    Recovered,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Sex {
+    Female,
+    Male,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Stage {
+    Juvenile,
+    Adult,
+}
+
+pub(crate) fn torus_offset(from: f64, to: f64) -> f64 {
+    // signed shortest displacement from one coordinate to another on the unit torus,
+    // in [-0.5, 0.5)
+    (to - from + 0.5).rem_euclid(1.0) - 0.5
+}
+
+#[derive(Clone)]
+pub struct Individual<'a> {
+    pub id: usize,
+    pub uid: usize,
+    pub species: &'a Species,
+    pub x_coord: f64,
+    pub y_coord: f64,
+    pub anchor: (f64, f64),
+    pub stage: Stage,
+    pub sex: Sex,
+    pub trait_value: f64,
+    pub birth_time: f64,
+    pub genome: Vec<u8>,
+    pub health: Health,
+    pub p_birth: f64,
+    pub p_death: f64,
+    pub p_move: f64,
+    pub p_mature: f64,
+    pub p_infect: f64,
+    pub p_recover: f64,
+    pub p_disease_death: f64,
+    // birth_neighbors: u32,
+    // death_neighbors: u32,
+    // kernel-weighted neighbor densities, kept up to date as neighbors arrive, leave,
+    // and move
+    pub birth_neighbor_density: f64,
+    pub death_neighbor_density: f64,
+    pub move_neighbor_density: f64,
+    pub birth_neighbor_weight: f64,
+    pub death_neighbor_weight: f64,
+    pub move_neighbor_weight: f64,
+    pub infection_pressure: f64,
+}
+
+impl<'a> Individual<'a> {
+    pub fn new(id: usize, species: &'a Species, x_coord: f64, y_coord: f64) -> Self {
+        Individual {
+            id,
+            uid: 0,
+            species,
+            x_coord,
+            y_coord,
+            anchor: (x_coord, y_coord),
+            stage: Stage::Adult,
+            sex: Sex::Female,
+            trait_value: species.T0,
+            birth_time: 0.0,
+            genome: vec![],
+            health: Health::Susceptible,
+            p_birth: 0.0,
+            p_death: 0.0,
+            p_move: 0.0,
+            p_mature: 0.0,
+            p_infect: 0.0,
+            p_recover: 0.0,
+            p_disease_death: 0.0,
+            // birth_neighbors: 0,
+            // death_neighbors: 0,
+            birth_neighbor_density: 0.0,
+            death_neighbor_density: 0.0,
+            move_neighbor_density: 0.0,
+            birth_neighbor_weight: 0.0,
+            death_neighbor_weight: 0.0,
+            move_neighbor_weight: 0.0,
+            infection_pressure: 0.0,
+        }
+    }
+
+    pub fn distance(&self, other: &Individual) -> f64 {
+        // Compute the Euclidean distance between the positions of two individuals
+
+        let inside_delta_x = (self.x_coord - other.x_coord).abs();
+        let delta_x = inside_delta_x.min(1.0 - inside_delta_x);
+
+        let inside_delta_y = (self.y_coord - other.y_coord).abs();
+        let delta_y = inside_delta_y.min(1.0 - inside_delta_y);
+
+        (delta_x.powi(2) + delta_y.powi(2)).sqrt()
+    }
+
+    pub fn kernel_weights(&self, distance: f64) -> [f64; 3] {
+        // Birth, death, and move kernel weights this individual gives a neighbor at
+        // the given distance
+
+        let species = self.species;
+        [
+            (species.Wbrmax, species.Wbsd),
+            (species.Wdrmax, species.Wdsd),
+            (species.Mrmax, species.Msd),
+        ]
+        .map(|(radius, sd)| {
+            let var = sd.powi(2);
+            let norm = kernels::truncated_gaussian_norm(radius, var);
+            kernels::truncated_gaussian(distance, radius, var, norm)
+        })
+    }
+
+    pub fn add_neighbor(&mut self, distance: f64, sign: f64) {
+        // Count a neighbor at the given distance into the densities, or with a negative
+        // sign take it back out
+
+        let [birth, death, movement] = self.kernel_weights(distance);
+        self.birth_neighbor_density += sign * birth;
+        self.death_neighbor_density += sign * death;
+        self.move_neighbor_density += sign * movement;
+    }
+
+    pub fn add_infected_neighbor(&mut self, distance: f64, sign: f64) {
+        // Count an infected neighbor at the given distance into the infection pressure
+        // under this individual's own transmission kernel, or take it back out
+
+        let var = self.species.Esd.powi(2);
+        let norm = kernels::truncated_gaussian_norm(self.species.Ermax, var);
+        self.infection_pressure +=
+            sign * kernels::truncated_gaussian(distance, self.species.Ermax, var, norm);
+    }
+
+    pub fn update_neighbor_weights(&mut self) {
+        // scale the neighbor densities by the individual's neighbor effects; densities
+        // and infection pressure are clamped at zero since removing neighbors one by one
+        // can leave a rounding residue just below it
+
+        self.birth_neighbor_density = self.birth_neighbor_density.max(0.0);
+        self.death_neighbor_density = self.death_neighbor_density.max(0.0);
+        self.move_neighbor_density = self.move_neighbor_density.max(0.0);
+        self.infection_pressure = self.infection_pressure.max(0.0);
+        self.birth_neighbor_weight = self.birth_neighbor_density * self.birth_params().1;
+        self.death_neighbor_weight = self.death_neighbor_density * self.death_params().1;
+        self.move_neighbor_weight = self.move_neighbor_density * self.species.M1;
+    }
+
+    pub fn allee_factor(&self) -> f64 {
+        // Scale births down by up to A1 when the local density is below the A0 threshold
+
+        if self.species.A0 <= 0.0 || self.species.A1 == 0.0 {
+            return 1.0;
+        }
+        let shortfall = 1.0 - (self.birth_neighbor_density / self.species.A0).min(1.0);
+        1.0 - self.species.A1 * shortfall
+    }
+
+    pub fn birth_params(&self) -> (f64, f64) {
+        // Baseline birth rate, shifted by the individual's trait, and neighbor effect for
+        // the individual's life stage
+
+        let (b0, b1) = match self.stage {
+            Stage::Juvenile => (self.species.JB0, self.species.JB1),
+            Stage::Adult => (self.species.B0, self.species.B1),
+        };
+        ((b0 + self.species.TB * self.trait_value).max(0.0), b1)
+    }
+
+    pub fn death_params(&self) -> (f64, f64) {
+        // Baseline death rate, shifted by the individual's trait, and neighbor effect for
+        // the individual's life stage
+
+        let (d0, d1) = match self.stage {
+            Stage::Juvenile => (self.species.JD0, self.species.JD1),
+            Stage::Adult => (self.species.D0, self.species.D1),
+        };
+        ((d0 + self.species.TD * self.trait_value).max(0.0), d1)
+    }
+
+    pub fn update_probabilities(&mut self, birth_quality: f64, death_quality: f64) {
+        // Update individual demographic and disease probabilities, scaling the baseline
+        // rates by the local habitat quality. Neighbor effects may be negative, so the
+        // rates they shift are clamped at zero rather than left to abort the run
+
+        // in two-sex species only females give birth
+        self.p_birth = if self.species.Rmate > 0.0 && self.sex == Sex::Male {
+            0.0
+        } else {
+            (self.birth_params().0 * birth_quality + self.birth_neighbor_weight).max(0.0)
+                * self.allee_factor()
+        };
+        self.p_death =
+            (self.death_params().0 * death_quality + self.death_neighbor_weight).max(0.0);
+        self.p_move = (self.species.Mintegral + self.move_neighbor_weight).max(0.0);
+        self.p_mature = match self.stage {
+            Stage::Juvenile => self.species.G0,
+            Stage::Adult => 0.0,
+        };
+
+        let infected = self.health == Health::Infected;
+        self.p_infect = match self.health {
+            Health::Susceptible => self.species.Ebeta * self.infection_pressure,
+            _ => 0.0,
+        };
+        self.p_recover = if infected { self.species.Egamma } else { 0.0 };
+        self.p_disease_death = if infected { self.species.Ealpha } else { 0.0 };
+    }
+
+    pub fn event_rates(&self) -> [(Event, f64); 7] {
+        [
+            (Event::Birth, self.p_birth),
+            (Event::Death, self.p_death),
+            (Event::Move, self.p_move),
+            (Event::Maturation, self.p_mature),
+            (Event::Infection, self.p_infect),
+            (Event::Recovery, self.p_recover),
+            (Event::DiseaseDeath, self.p_disease_death),
+        ]
+    }
+}
@@ -0,0 +1,155 @@
+use std::collections::HashSet;
+
+use crate::analysis::torus_distance;
+use crate::individual::Health;
+use crate::population::DIAGONAL;
+use crate::sampling::RateTree;
+use crate::{kernels, Population, SimError};
+
+// largest accepted gap between a stored value and its recomputation, relative to the
+// larger of the value and one
+const TOLERANCE: f64 = 1e-9;
+
+impl<'a> Population<'a> {
+    pub fn validate(&self) -> Result<(), SimError> {
+        // Check the population's bookkeeping against a from-scratch recomputation: sizes,
+        // ids and uids, positions, the grid, the queued rate updates, the running neighbor
+        // densities and infection pressure, and the current rates and their tree. This
+        // costs O(n^2), so step only runs it after every event in debug builds
+
+        let violation = |message: String| Err(SimError::Invariant(message));
+        let close = |stored: f64, expected: f64| {
+            (stored - expected).abs() <= TOLERANCE * stored.abs().max(expected.abs()).max(1.0)
+        };
+
+        if self.individuals.len() != self.size
+            || self.grid.len() != self.size
+            || self.rates.len() != self.size
+        {
+            return violation(format!(
+                "size {} but {} individuals, {} in the grid and {} rates",
+                self.size,
+                self.individuals.len(),
+                self.grid.len(),
+                self.rates.len()
+            ));
+        }
+        let mut uids = HashSet::new();
+        for (idx, individual) in self.individuals.iter().enumerate() {
+            if individual.id != idx {
+                return violation(format!("individual at {idx} has id {}", individual.id));
+            }
+            if !uids.insert(individual.uid) {
+                return violation(format!("uid {} is used twice", individual.uid));
+            }
+            let (x, y) = (individual.x_coord, individual.y_coord);
+            if !(0.0..1.0).contains(&x) || !(0.0..1.0).contains(&y) {
+                return violation(format!("uid {} is at ({x}, {y})", individual.uid));
+            }
+            if !self.grid.contains(idx, x, y) {
+                return violation(format!(
+                    "uid {} is missing from its grid cell",
+                    individual.uid
+                ));
+            }
+        }
+        // a death moves the last individual into the freed slot, and its queued update
+        // with it
+        if let Some(idx) = self.stale.iter().find(|x| **x >= self.size) {
+            return violation(format!(
+                "rate update queued for index {idx} of {} individuals",
+                self.size
+            ));
+        }
+
+        for i in 0..self.size {
+            let first = &self.individuals[i];
+            let mut densities = [0.0; 4];
+            let var = first.species.Esd.powi(2);
+            let norm = kernels::truncated_gaussian_norm(first.species.Ermax, var);
+            for j in 0..self.size {
+                let second = &self.individuals[j];
+                let distance = if i == j {
+                    DIAGONAL
+                } else {
+                    torus_distance(
+                        (first.x_coord, first.y_coord),
+                        (second.x_coord, second.y_coord),
+                    )
+                };
+                for (density, weight) in densities.iter_mut().zip(first.kernel_weights(distance)) {
+                    *density += weight;
+                }
+                if i != j && second.health == Health::Infected {
+                    densities[3] +=
+                        kernels::truncated_gaussian(distance, first.species.Ermax, var, norm);
+                }
+            }
+            let stored = [
+                first.birth_neighbor_density,
+                first.death_neighbor_density,
+                first.move_neighbor_density,
+                first.infection_pressure,
+            ];
+            for (kind, (stored, expected)) in [
+                "birth neighbor density",
+                "death neighbor density",
+                "move neighbor density",
+                "infection pressure",
+            ]
+            .iter()
+            .zip(stored.iter().zip(densities))
+            {
+                // these are only clamped at zero when the rates are next updated
+                if !close(stored.max(0.0), expected) {
+                    return violation(format!(
+                        "uid {} has {kind} {stored} but should have {expected}",
+                        first.uid
+                    ));
+                }
+            }
+        }
+
+        // rates awaiting an update are left to it
+        if !self.all_stale && self.throttled() == self.rates_throttled {
+            for (idx, individual) in self.individuals.iter().enumerate() {
+                if self.stale.contains(&idx) {
+                    continue;
+                }
+                let mut expected = individual.clone();
+                let (birth_quality, death_quality) =
+                    self.habitat_quality(individual.x_coord, individual.y_coord);
+                expected.update_neighbor_weights();
+                expected.update_probabilities(birth_quality, death_quality);
+                if self.rates_throttled {
+                    expected.p_birth = 0.0;
+                }
+                let total: f64 = expected.event_rates().iter().map(|x| x.1).sum();
+                for ((event, stored), (_, rate)) in individual
+                    .event_rates()
+                    .into_iter()
+                    .zip(expected.event_rates())
+                {
+                    if !close(stored, rate) {
+                        return violation(format!(
+                            "uid {} has {event:?} rate {stored} but should have {rate}",
+                            individual.uid
+                        ));
+                    }
+                }
+                if !close(self.rates.rate(idx), total) {
+                    return violation(format!(
+                        "uid {} has total rate {} but should have {total}",
+                        individual.uid,
+                        self.rates.rate(idx)
+                    ));
+                }
+            }
+        }
+        if self.rates != RateTree::from_rates((0..self.size).map(|x| self.rates.rate(x))) {
+            return violation("rate tree differs from one rebuilt from its rates".to_string());
+        }
+
+        self.check_rates()
+    }
+}
@@ -0,0 +1,108 @@
+use rand::Rng;
+use std::f64::consts::PI;
+
+pub fn truncated_gaussian_norm(radius: f64, var: f64) -> f64 {
+    // Integral over the plane of a 2D Gaussian with variance `var` truncated at `radius`
+
+    if var == 0.0 {
+        0.0
+    } else {
+        2.0 * var * PI * (1.0 - (-radius.powi(2) / (2.0 * var)).exp())
+    }
+}
+
+pub fn truncated_gaussian(distance: f64, radius: f64, var: f64, norm: f64) -> f64 {
+    // Normalized kernel weight at `distance`, zero outside the truncation radius
+
+    if var == 0.0 || norm == 0.0 || distance >= radius {
+        0.0
+    } else {
+        (-distance.powi(2) / (2.0 * var)).exp() / norm
+    }
+}
+
+pub fn sample_truncated_gaussian_offset(rng: &mut impl Rng, sd: f64, radius: f64) -> (f64, f64) {
+    // Draw a displacement from a 2D isotropic Gaussian truncated at `radius` (untruncated
+    // if radius <= 0) by inverting the Rayleigh CDF of its length; a zero sd places the
+    // offspring exactly at the parent
+
+    if sd == 0.0 {
+        return (0.0, 0.0);
+    }
+    let var = sd.powi(2);
+    let mass = if radius > 0.0 {
+        1.0 - (-radius.powi(2) / (2.0 * var)).exp()
+    } else {
+        1.0
+    };
+    let u: f64 = rng.gen();
+    let distance = (-2.0 * var * (1.0 - u * mass).ln()).sqrt();
+    let angle = 2.0 * PI * rng.gen::<f64>();
+    (distance * angle.cos(), distance * angle.sin())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha12Rng;
+
+    // (radius, var) pairs from a kernel much narrower than its radius to one much wider
+    const CASES: [(f64, f64); 4] = [(0.1, 0.0025), (0.1, 0.01), (0.3, 0.001), (0.05, 0.04)];
+
+    fn radial_integral(radius: f64, f: impl Fn(f64) -> f64) -> f64 {
+        // composite Simpson's rule for the integral of f(r) 2 pi r dr over [0, radius]; the
+        // kernels are cut off at the radius itself, so the last node is taken just inside
+        let n = 2000;
+        let h = radius / n as f64;
+        let g = |r: f64| f(r) * 2.0 * PI * r;
+        let mut total = g(0.0) + g(radius * (1.0 - 1e-12));
+        for k in 1..n {
+            total += if k % 2 == 1 { 4.0 } else { 2.0 } * g(k as f64 * h);
+        }
+        total * h / 3.0
+    }
+
+    #[test]
+    fn norm_matches_quadrature() {
+        for (radius, var) in CASES {
+            let expected = radial_integral(radius, |r| (-r.powi(2) / (2.0 * var)).exp());
+            let norm = truncated_gaussian_norm(radius, var);
+            assert!((norm - expected).abs() < 1e-10 * expected, "{radius} {var}");
+        }
+    }
+
+    #[test]
+    fn kernel_integrates_to_one() {
+        for (radius, var) in CASES {
+            let norm = truncated_gaussian_norm(radius, var);
+            let total = radial_integral(radius, |r| truncated_gaussian(r, radius, var, norm));
+            assert!((total - 1.0).abs() < 1e-9, "{radius} {var}: {total}");
+            assert_eq!(truncated_gaussian(radius, radius, var, norm), 0.0);
+        }
+    }
+
+    #[test]
+    fn sampled_offsets_follow_the_kernel() {
+        // the mean distance of sampled offsets against the first moment of the kernel
+        let mut rng = ChaCha12Rng::seed_from_u64(1);
+        let samples = 100_000;
+        for (radius, var) in CASES {
+            let norm = truncated_gaussian_norm(radius, var);
+            let expected =
+                radial_integral(radius, |r| r * truncated_gaussian(r, radius, var, norm));
+            let mut total = 0.0;
+            for _ in 0..samples {
+                let (dx, dy) = sample_truncated_gaussian_offset(&mut rng, var.sqrt(), radius);
+                let distance = (dx * dx + dy * dy).sqrt();
+                assert!(distance < radius);
+                total += distance;
+            }
+            let mean = total / samples as f64;
+            assert!(
+                (mean - expected).abs() < 0.01 * expected,
+                "{radius} {var}: {mean}"
+            );
+        }
+    }
+}
@@ -0,0 +1,95 @@
+use rand::Rng;
+
+use crate::sampling::sample_poisson;
+use crate::streams::Stream;
+use crate::{Event, Population, SimError, Stepping};
+
+impl<'a> Population<'a> {
+    pub(crate) fn progress(&mut self, max_t: f64) -> Result<bool, SimError> {
+        // take one exact step or one leap, as configured, going no further than max_t;
+        // false once nothing can happen before it
+
+        match self.stepping {
+            Stepping::Exact => self.step_towards(max_t),
+            Stepping::TauLeap { epsilon } => self.leap(epsilon, max_t),
+        }
+    }
+
+    fn step_towards(&mut self, max_t: f64) -> Result<bool, SimError> {
+        // an exact step, counting the clock stopping at max_t as progress
+        Ok(self.step_within(max_t)?.is_some() || self.t >= max_t)
+    }
+
+    fn leap(&mut self, epsilon: f64, max_t: f64) -> Result<bool, SimError> {
+        // Tau-leaping: hold every rate fixed over a leap of length tau = epsilon * n /
+        // total rate (cut short at max_t), so that about epsilon * n events are expected,
+        // and fire each
+        // individual's events as Poisson counts over the leap. Deaths, maturation,
+        // infection and recovery happen at most once per individual; births and moves may
+        // repeat. Exact steps are taken instead when a leap would batch less than one
+        // event or would pass a scheduled death, harvest or introduction.
+
+        self.update_rates()?;
+        let immigration: Vec<f64> = self
+            .species
+            .iter()
+            .map(|x| self.immigration_rate(x))
+            .collect();
+        let total = self.total_rate();
+        let expected = epsilon * self.size as f64;
+        if total <= 0.0 || expected < 1.0 {
+            return self.step_towards(max_t);
+        }
+        let tau = (expected / total).min(max_t - self.t);
+        let scheduled = [
+            self.next_expiry().map(|x| x.1),
+            self.next_harvest().map(|x| x.1),
+            self.next_introduction().map(|x| x.1),
+        ];
+        if scheduled.into_iter().flatten().any(|at| at < self.t + tau) {
+            return self.step_towards(max_t);
+        }
+
+        // draw every event of the leap against the rates at its start
+        let rng = self.streams.get(Stream::Events);
+        let mut events = vec![];
+        let mut deaths = vec![];
+        for (idx, individual) in self.individuals.iter().enumerate() {
+            let mut dies = None;
+            for (event, rate) in individual.event_rates() {
+                if rate <= 0.0 {
+                    continue;
+                }
+                match event {
+                    Event::Birth | Event::Move => {
+                        for _ in 0..sample_poisson(rng, rate * tau) {
+                            events.push((event, idx));
+                        }
+                    }
+                    _ if rng.gen::<f64>() >= 1.0 - (-rate * tau).exp() => (),
+                    Event::Death | Event::DiseaseDeath => dies = dies.or(Some(event)),
+                    _ => events.push((event, idx)),
+                }
+            }
+            if let Some(event) = dies {
+                deaths.push((event, idx));
+            }
+        }
+        for (idx, rate) in immigration.iter().enumerate() {
+            for _ in 0..sample_poisson(rng, rate * tau) {
+                events.push((Event::Immigration, idx));
+            }
+        }
+
+        // births and arrivals append, so indices stay valid until the deaths, which are
+        // carried out from the back
+        self.advance(tau);
+        for (event, idx) in events {
+            self.execute(event, idx);
+        }
+        for (event, idx) in deaths.into_iter().rev() {
+            self.execute(event, idx);
+        }
+        Ok(true)
+    }
+}
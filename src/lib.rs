@@ -0,0 +1,48 @@
+pub mod analysis;
+mod archive;
+pub mod bench;
+mod checkpoints;
+pub mod cli;
+pub mod config;
+pub mod error;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod fit;
+pub mod genealogy;
+pub mod genetics;
+pub mod golden;
+mod grid;
+pub mod habitat;
+pub mod heatmap;
+pub mod history;
+pub mod individual;
+mod invariants;
+pub mod kernels;
+mod leap;
+pub mod loader;
+pub mod metadata;
+pub mod moments;
+pub mod ndjson;
+pub mod obstacle;
+pub mod pattern;
+pub mod population;
+pub mod profile;
+pub mod replay;
+pub mod resource;
+mod sampling;
+pub mod schedule;
+mod snapshot;
+pub mod species;
+mod steps;
+mod streams;
+pub mod sweep;
+pub mod watch;
+
+pub use checkpoints::{Batches, Checkpoints, FlushPolicy};
+pub use config::SimulationConfig;
+pub use error::SimError;
+pub use history::{Checkpoint, History};
+pub use individual::Individual;
+pub use population::{CapPolicy, Event, EventRecord, Population, Stepping, StopReason};
+pub use species::{Species, SpeciesError};
+pub use steps::Steps;
@@ -0,0 +1,412 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::pattern::Pattern;
+use crate::Species;
+
+// Species parameter files, one species per CSV row, JSON object or TOML [[species]]
+// table, keyed by the Species field names, with colours given as "#rrggbb" and the
+// initial pattern as one of "uniform", "grid", "thomas <parents> <sd>", "point <x> <y>"
+// or "explicit <x1> <y1> <x2> <y2> ...". Omitted fields take their defaults, and a
+// species without an id is numbered by its position in the file. Kernel norms are not
+// read since they are always derived from the radii and sds.
+
+fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+pub(crate) fn f64_field<'s>(species: &'s mut Species, name: &str) -> Option<&'s mut f64> {
+    let field = match name {
+        "B0" => &mut species.B0,
+        "B1" => &mut species.B1,
+        "C1" => &mut species.C1,
+        "D0" => &mut species.D0,
+        "D1" => &mut species.D1,
+        "M1" => &mut species.M1,
+        "A0" => &mut species.A0,
+        "A1" => &mut species.A1,
+        "Rexcl" => &mut species.Rexcl,
+        "I0" => &mut species.I0,
+        "Mdsd" => &mut species.Mdsd,
+        "Mbrmax" => &mut species.Mbrmax,
+        "Mbsd" => &mut species.Mbsd,
+        "Mintegral" => &mut species.Mintegral,
+        "Mrmax" => &mut species.Mrmax,
+        "Msd" => &mut species.Msd,
+        "Wbrmax" => &mut species.Wbrmax,
+        "Wbsd" => &mut species.Wbsd,
+        "Wdrmax" => &mut species.Wdrmax,
+        "Wdsd" => &mut species.Wdsd,
+        "G0" => &mut species.G0,
+        "JB0" => &mut species.JB0,
+        "JB1" => &mut species.JB1,
+        "JD0" => &mut species.JD0,
+        "JD1" => &mut species.JD1,
+        "Rmate" => &mut species.Rmate,
+        "T0" => &mut species.T0,
+        "Tmut" => &mut species.Tmut,
+        "TB" => &mut species.TB,
+        "TD" => &mut species.TD,
+        "Lmax" => &mut species.Lmax,
+        "Nmut" => &mut species.Nmut,
+        "Eprev" => &mut species.Eprev,
+        "Ebeta" => &mut species.Ebeta,
+        "Ermax" => &mut species.Ermax,
+        "Esd" => &mut species.Esd,
+        "Egamma" => &mut species.Egamma,
+        "Ealpha" => &mut species.Ealpha,
+        "Clutch" => &mut species.Clutch,
+        "Hrange" => &mut species.Hrange,
+        "Xcon" => &mut species.Xcon,
+        "Xhet" => &mut species.Xhet,
+        _ => return None,
+    };
+    Some(field)
+}
+
+fn parse_color(value: &str) -> Option<[u8; 3]> {
+    let hex = value.strip_prefix('#')?;
+    if hex.len() != 6 || !hex.is_ascii() {
+        return None;
+    }
+    let channel = |k: usize| u8::from_str_radix(&hex[2 * k..2 * k + 2], 16).ok();
+    Some([channel(0)?, channel(1)?, channel(2)?])
+}
+
+fn parse_pattern(value: &str) -> Option<Pattern> {
+    let mut words = value.split_whitespace();
+    let kind = words.next()?.to_lowercase();
+    let numbers: Vec<f64> = words.map(|x| x.parse().ok()).collect::<Option<_>>()?;
+    let pattern = match (kind.as_str(), &numbers[..]) {
+        ("uniform", []) => Pattern::Uniform,
+        ("grid", []) => Pattern::Grid,
+        ("thomas", &[parents, sd]) if parents >= 1.0 && parents.fract() == 0.0 && sd >= 0.0 => {
+            Pattern::Thomas {
+                parents: parents as usize,
+                sd,
+            }
+        }
+        ("point", &[x, y]) => Pattern::Point(x, y),
+        ("explicit", coords) if coords.len() % 2 == 0 => {
+            Pattern::Explicit(coords.chunks(2).map(|p| (p[0], p[1])).collect())
+        }
+        _ => return None,
+    };
+    Some(pattern)
+}
+
+pub(crate) fn set_field(species: &mut Species, name: &str, value: &str) -> Result<(), String> {
+    let parse_error = |e: &dyn std::fmt::Display| format!("{name} = {value:?}: {e}");
+    let parse_bool = || match value.to_lowercase().as_str() {
+        "true" | "1" => Ok(true),
+        "false" | "0" => Ok(false),
+        _ => Err(format!("{name} = {value:?}: expected true or false")),
+    };
+    match name {
+        "id" => species.id = value.parse().map_err(|e| parse_error(&e))?,
+        "Nalleles" => species.Nalleles = value.parse().map_err(|e| parse_error(&e))?,
+        "Nloci" => species.Nloci = value.parse().map_err(|e| parse_error(&e))?,
+        "Iedge" => species.Iedge = parse_bool()?,
+        "Eimmune" => species.Eimmune = parse_bool()?,
+        "name" => species.name = Some(value.to_string()),
+        "color" => {
+            species.color = Some(parse_color(value).ok_or(parse_error(&"expected #rrggbb"))?)
+        }
+        "Init" => {
+            species.Init =
+                parse_pattern(value).ok_or(parse_error(&"expected an initial pattern"))?
+        }
+        _ => {
+            let field = f64_field(species, name).ok_or(format!("unknown field {name:?}"))?;
+            *field = value.parse().map_err(|e| parse_error(&e))?;
+        }
+    }
+    Ok(())
+}
+
+fn build(records: Vec<Vec<(String, String)>>) -> Result<Vec<Species>, String> {
+    // turn each record of (field, value) pairs into a validated species
+
+    let mut species_list = vec![];
+    for (idx, record) in records.into_iter().enumerate() {
+        let mut species = Species {
+            id: idx as u8,
+            ..Default::default()
+        };
+        for (name, value) in &record {
+            set_field(&mut species, name, value).map_err(|e| format!("species {idx}: {e}"))?;
+        }
+        let species = Species::new(species).map_err(|e| format!("species {idx}: {e}"))?;
+        // ids index per-species tables throughout a run, so they must be unique
+        if let Some(other) = species_list
+            .iter()
+            .position(|x: &Species| x.id == species.id)
+        {
+            return Err(format!(
+                "species {idx}: id {} is already used by species {other}",
+                species.id
+            ));
+        }
+        species_list.push(species);
+    }
+    Ok(species_list)
+}
+
+fn csv_cells(line: &str) -> Result<Vec<String>, String> {
+    // The cells of a CSV row, trimmed unless quoted; a quoted cell may hold commas and
+    // doubled quotes, as written by history::csv_field
+
+    let mut cells = vec![];
+    let mut rest = line;
+    loop {
+        let trimmed = rest.trim_start();
+        let (cell, after) = match trimmed.strip_prefix('"') {
+            Some(quoted) => {
+                let mut cell = String::new();
+                let mut chars = quoted.char_indices();
+                let end = loop {
+                    match chars.next() {
+                        Some((i, '"')) if quoted[i + 1..].starts_with('"') => {
+                            cell.push('"');
+                            chars.next();
+                        }
+                        Some((i, '"')) => break i + 1,
+                        Some((_, c)) => cell.push(c),
+                        None => return Err(format!("unterminated quote in {line:?}")),
+                    }
+                };
+                let after = quoted[end..].trim_start();
+                if !(after.is_empty() || after.starts_with(',')) {
+                    return Err(format!("text after a closing quote in {line:?}"));
+                }
+                (cell, after)
+            }
+            None => {
+                let end = trimmed.find(',').unwrap_or(trimmed.len());
+                (trimmed[..end].trim().to_string(), &trimmed[end..])
+            }
+        };
+        cells.push(cell);
+        match after.strip_prefix(',') {
+            Some(next) => rest = next,
+            None => return Ok(cells),
+        }
+    }
+}
+
+pub fn species_from_csv(text: &str) -> io::Result<Vec<Species>> {
+    // Header row of field names followed by one row per species; empty cells are
+    // left at their defaults, and cells may be quoted
+
+    let mut lines = text.lines().filter(|l| !l.trim().is_empty());
+    let Some(header) = lines.next() else {
+        return Ok(vec![]);
+    };
+    let names = csv_cells(header).map_err(invalid)?;
+    let mut records = vec![];
+    for (row, line) in lines.enumerate() {
+        let cells = csv_cells(line).map_err(|e| invalid(format!("row {}: {e}", row + 1)))?;
+        if cells.len() != names.len() {
+            return Err(invalid(format!(
+                "row {} has {} cells but the header has {}",
+                row + 1,
+                cells.len(),
+                names.len()
+            )));
+        }
+        records.push(
+            names
+                .iter()
+                .zip(cells)
+                .filter(|(_, cell)| !cell.is_empty())
+                .map(|(name, cell)| (name.clone(), cell))
+                .collect(),
+        );
+    }
+    build(records).map_err(invalid)
+}
+
+pub(crate) fn strip_comment(line: &str) -> &str {
+    // a TOML comment runs from the first # outside a quoted string
+
+    let mut quoted = false;
+    let end = line
+        .find(|c| {
+            quoted ^= c == '"';
+            c == '#' && !quoted
+        })
+        .unwrap_or(line.len());
+    line[..end].trim()
+}
+
+pub fn species_from_toml(text: &str) -> io::Result<Vec<Species>> {
+    // A [[species]] table per species holding `field = value` lines; only this subset
+    // of TOML is understood
+
+    let mut records: Vec<Vec<(String, String)>> = vec![];
+    for (number, line) in text.lines().enumerate() {
+        let line = strip_comment(line);
+        if line.is_empty() {
+            continue;
+        }
+        if line == "[[species]]" {
+            records.push(vec![]);
+            continue;
+        }
+        let (Some((name, value)), Some(record)) = (line.split_once('='), records.last_mut()) else {
+            return Err(invalid(format!(
+                "line {}: expected a [[species]] table or a `field = value` line",
+                number + 1
+            )));
+        };
+        let value = value.trim().trim_matches('"');
+        record.push((name.trim().to_string(), value.to_string()));
+    }
+    build(records).map_err(invalid)
+}
+
+struct Json<'t> {
+    text: &'t str,
+    position: usize,
+}
+
+impl Json<'_> {
+    fn skip_whitespace(&mut self) {
+        let rest = &self.text[self.position..];
+        self.position += rest.len() - rest.trim_start().len();
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.skip_whitespace();
+        self.text[self.position..].chars().next()
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), String> {
+        if self.peek() != Some(c) {
+            return Err(format!("expected {c:?} at byte {}", self.position));
+        }
+        self.position += c.len_utf8();
+        Ok(())
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        // escapes are not needed for field names or values and are not supported
+        self.expect('"')?;
+        let rest = &self.text[self.position..];
+        let end = rest
+            .find('"')
+            .ok_or(format!("unterminated string at byte {}", self.position))?;
+        self.position += end + 1;
+        Ok(rest[..end].to_string())
+    }
+
+    fn value(&mut self) -> Result<String, String> {
+        if self.peek() == Some('"') {
+            return self.string();
+        }
+        let rest = &self.text[self.position..];
+        let end = rest
+            .find(|c: char| c == ',' || c == '}' || c.is_whitespace())
+            .unwrap_or(rest.len());
+        if end == 0 {
+            return Err(format!("expected a value at byte {}", self.position));
+        }
+        self.position += end;
+        Ok(rest[..end].to_string())
+    }
+
+    fn record(&mut self) -> Result<Vec<(String, String)>, String> {
+        let mut record = vec![];
+        self.expect('{')?;
+        if self.peek() == Some('}') {
+            self.position += 1;
+            return Ok(record);
+        }
+        loop {
+            let name = self.string()?;
+            self.expect(':')?;
+            let value = self.value()?;
+            // null leaves the field at its default
+            if value != "null" {
+                record.push((name, value));
+            }
+            if self.peek() == Some(',') {
+                self.position += 1;
+            } else {
+                self.expect('}')?;
+                return Ok(record);
+            }
+        }
+    }
+}
+
+pub fn species_from_json(text: &str) -> io::Result<Vec<Species>> {
+    // An array of flat objects, one per species
+
+    let mut json = Json { text, position: 0 };
+    let mut records = vec![];
+    json.expect('[').map_err(invalid)?;
+    if json.peek() != Some(']') {
+        loop {
+            records.push(json.record().map_err(invalid)?);
+            if json.peek() == Some(',') {
+                json.position += 1;
+            } else {
+                break;
+            }
+        }
+    }
+    json.expect(']').map_err(invalid)?;
+    if json.peek().is_some() {
+        return Err(invalid(format!(
+            "unexpected content at byte {}",
+            json.position
+        )));
+    }
+    build(records).map_err(invalid)
+}
+
+pub fn load_species(path: impl AsRef<Path>) -> io::Result<Vec<Species>> {
+    // Read a species file, choosing the format from its extension
+
+    let path = path.as_ref();
+    let text = fs::read_to_string(path)?;
+    match path.extension().and_then(|x| x.to_str()) {
+        Some("csv") => species_from_csv(&text),
+        Some("json") => species_from_json(&text),
+        Some("toml") => species_from_toml(&text),
+        _ => Err(invalid(format!(
+            "{}: expected a .csv, .json or .toml species file",
+            path.display()
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history::csv_field;
+
+    #[test]
+    fn csv_names_round_trip_through_quoting() {
+        // names quoted by csv_field for the history and summary exports read back as
+        // they were written
+        let names = ["plain", "comma, separated", "say \"hi\"", " padded ", ""];
+        let mut text = String::from("id,name,B0\n");
+        for (id, name) in names.iter().enumerate() {
+            text += &format!("{id},{},0.5\n", csv_field(name));
+        }
+        let species = species_from_csv(&text).unwrap();
+        assert_eq!(species.len(), names.len());
+        for (species, name) in species.iter().zip(names) {
+            // quoting does not protect surrounding spaces, and an empty cell is unset
+            let expected = Some(name.trim()).filter(|x| !x.is_empty());
+            assert_eq!(species.name.as_deref(), expected);
+            assert_eq!(species.B0, 0.5);
+        }
+
+        assert!(species_from_csv("id,name\n0,\"open\n").is_err());
+        assert!(species_from_csv("id,name\n0,\"a\"b\n").is_err());
+    }
+}
@@ -0,0 +1,298 @@
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use ndarray::Array3;
+
+use crate::archive::Encoder;
+use crate::heatmap;
+use crate::obstacle::Obstacle;
+use crate::pattern::Pattern;
+use crate::{CapPolicy, Population, SimulationConfig, Species, Stepping};
+
+pub struct RunMetadata {
+    pub run_id: String,
+    pub seed: u64,
+    pub parameter_hash: u64,
+}
+
+pub fn sidecar_path(artifact: impl AsRef<Path>) -> PathBuf {
+    // where the metadata of an exported file goes: its own name plus .meta.json
+    let mut path = artifact.as_ref().as_os_str().to_owned();
+    path.push(".meta.json");
+    PathBuf::from(path)
+}
+
+impl RunMetadata {
+    pub fn to_json(&self, artifact: &str) -> String {
+        let artifact = artifact.replace('\\', "\\\\").replace('"', "\\\"");
+        format!(
+            "{{\"artifact\":\"{artifact}\",\"run_id\":\"{}\",\"seed\":{},\"parameter_hash\":\"{:016x}\"}}",
+            self.run_id, self.seed, self.parameter_hash
+        )
+    }
+
+    pub fn write_sidecar(&self, artifact: impl AsRef<Path>) -> io::Result<()> {
+        // Record which run an exported file came from in a JSON file next to it, so the
+        // file stays traceable whatever its format
+
+        let artifact = artifact.as_ref();
+        let name = artifact
+            .file_name()
+            .map_or(String::new(), |x| x.to_string_lossy().into_owned());
+        fs::write(sidecar_path(artifact), self.to_json(&name) + "\n")
+    }
+}
+
+fn fnv1a(bytes: &[u8]) -> u64 {
+    // FNV-1a, used instead of std's hasher because its output must not change between
+    // Rust versions for hashes in old artifacts to stay comparable
+
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+fn encode_species(encoder: &mut Encoder, species: &Species) {
+    // Every parameter that affects a run, in a fixed order; names and colours only
+    // label the outputs and are left out
+
+    encoder.u8(species.id);
+    for value in [
+        species.B0,
+        species.B1,
+        species.C1,
+        species.D0,
+        species.D1,
+        species.M1,
+        species.A0,
+        species.A1,
+        species.Rexcl,
+        species.I0,
+    ] {
+        encoder.f64(value);
+    }
+    encoder.u8(species.Iedge as u8);
+    for value in [
+        species.Mdsd,
+        species.Mbrmax,
+        species.Mbsd,
+        species.Mintegral,
+        species.Mrmax,
+        species.Msd,
+        species.Wbrmax,
+        species.Wbsd,
+        species.Wdrmax,
+        species.Wdsd,
+        species.G0,
+        species.JB0,
+        species.JB1,
+        species.JD0,
+        species.JD1,
+        species.Rmate,
+        species.T0,
+        species.Tmut,
+        species.TB,
+        species.TD,
+        species.Lmax,
+    ] {
+        encoder.f64(value);
+    }
+    encoder.u64(species.Nloci);
+    encoder.u8(species.Nalleles);
+    for value in [
+        species.Nmut,
+        species.Eprev,
+        species.Ebeta,
+        species.Ermax,
+        species.Esd,
+        species.Egamma,
+        species.Ealpha,
+    ] {
+        encoder.f64(value);
+    }
+    encoder.u8(species.Eimmune as u8);
+    for value in [species.Clutch, species.Hrange, species.Xcon, species.Xhet] {
+        encoder.f64(value);
+    }
+    match &species.Init {
+        Pattern::Uniform => encoder.u8(0),
+        Pattern::Thomas { parents, sd } => {
+            encoder.u8(1);
+            encoder.u64(*parents);
+            encoder.f64(*sd);
+        }
+        Pattern::Grid => encoder.u8(2),
+        Pattern::Point(x, y) => {
+            encoder.u8(3);
+            encoder.f64(*x);
+            encoder.f64(*y);
+        }
+        Pattern::Explicit(coords) => {
+            encoder.u8(4);
+            encoder.u64(coords.len());
+            for (x, y) in coords {
+                encoder.f64(*x);
+                encoder.f64(*y);
+            }
+        }
+    }
+}
+
+pub(crate) fn species_hash(species_list: &[&Species]) -> u64 {
+    let mut encoder = Encoder { bytes: vec![] };
+    for species in species_list {
+        encode_species(&mut encoder, species);
+    }
+    fnv1a(&encoder.bytes)
+}
+
+pub(crate) fn settings_hash(config: &SimulationConfig) -> u64 {
+    // The species and every setting of a configuration but its seed, in declaration
+    // order; the seed is mixed in by Population::parameter_hash so that a population
+    // keeps a consistent hash when it resumes another run's state
+
+    let mut encoder = Encoder { bytes: vec![] };
+    encoder.u64(config.species.len());
+    for species in &config.species {
+        encode_species(&mut encoder, species);
+    }
+    encoder.f64(config.max_t);
+    encoder.f64(config.burn_in);
+    encoder.f64(config.checkpoint_interval);
+    encoder.flag(&config.output_times);
+    if let Some(times) = &config.output_times {
+        encoder.u64(times.len());
+        times.iter().for_each(|t| encoder.f64(*t));
+    }
+    encoder.u8(config.precision as u8);
+    encoder.u8(config.event_log as u8);
+    encoder.flag(&config.heatmap);
+    if let Some(kde) = &config.heatmap {
+        encoder.u64(kde.resolution);
+        encoder.f64(kde.bandwidth);
+        encoder.flag(&kde.species);
+        encoder.u8(kde.species.unwrap_or(0));
+    }
+    encoder.flag(&config.size_cap);
+    if let Some((limit, policy)) = config.size_cap {
+        encoder.u64(limit);
+        encoder.u8(matches!(policy, CapPolicy::Throttle) as u8);
+    }
+    match config.stepping {
+        Stepping::Exact => encoder.u8(0),
+        Stepping::TauLeap { epsilon } => {
+            encoder.u8(1);
+            encoder.f64(epsilon);
+        }
+    }
+    encoder.flag(&config.stationarity);
+    if let Some(stationarity) = &config.stationarity {
+        encoder.f64(stationarity.window);
+        encoder.f64(stationarity.tolerance);
+    }
+    for habitat in [&config.birth_habitat, &config.death_habitat] {
+        encoder.flag(habitat);
+        if let Some(habitat) = habitat {
+            let (rows, cols) = habitat.quality().dim();
+            encoder.u64(rows);
+            encoder.u64(cols);
+            habitat.quality().iter().for_each(|x| encoder.f64(*x));
+        }
+    }
+    encoder.flag(&config.resource);
+    if let Some(resource) = &config.resource {
+        let (rows, cols) = resource.level.dim();
+        encoder.u64(rows);
+        encoder.u64(cols);
+        resource.level.iter().for_each(|x| encoder.f64(*x));
+        encoder.f64(resource.capacity);
+        encoder.f64(resource.growth);
+        encoder.f64(resource.consumption);
+    }
+    encoder.u64(config.obstacles.len());
+    for obstacle in &config.obstacles {
+        match obstacle {
+            Obstacle::Rectangle {
+                x_min,
+                y_min,
+                x_max,
+                y_max,
+            } => {
+                encoder.u8(0);
+                [x_min, y_min, x_max, y_max]
+                    .iter()
+                    .for_each(|x| encoder.f64(**x));
+            }
+            Obstacle::Polygon(vertices) => {
+                encoder.u8(1);
+                encoder.u64(vertices.len());
+                for (x, y) in vertices {
+                    encoder.f64(*x);
+                    encoder.f64(*y);
+                }
+            }
+        }
+    }
+    encoder.u64(config.harvests.len());
+    for harvest in &config.harvests {
+        encoder.f64(harvest.t);
+        encoder.u8(harvest.species);
+        encoder.f64(harvest.fraction);
+        encoder.flag(&harvest.region);
+        harvest
+            .region
+            .iter()
+            .flatten()
+            .for_each(|x| encoder.f64(*x));
+    }
+    encoder.u64(config.introductions.len());
+    for introduction in &config.introductions {
+        encoder.f64(introduction.t);
+        encoder.u8(introduction.species.id);
+        encoder.u64(introduction.founders);
+        encoder.f64(introduction.location.0);
+        encoder.f64(introduction.location.1);
+        encoder.f64(introduction.spread);
+    }
+    fnv1a(&encoder.bytes)
+}
+
+impl<'a> Population<'a> {
+    pub fn parameter_hash(&self) -> u64 {
+        // Hash of the seed and the configuration the population was built from (for one
+        // built without a config, its species alone). Settings changed afterwards through
+        // the setters are not included
+
+        let mut bytes = self.settings_hash.to_le_bytes().to_vec();
+        bytes.extend(self.seed.to_le_bytes());
+        fnv1a(&bytes)
+    }
+
+    pub fn save_history(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        self.history.save(&path)?;
+        self.metadata().write_sidecar(path)
+    }
+
+    pub fn write_ndjson(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut file = BufWriter::new(File::create(&path)?);
+        self.history.to_ndjson(&mut file)?;
+        file.flush()?;
+        self.metadata().write_sidecar(path)
+    }
+
+    pub fn write_ppm(&self, image: &Array3<u8>, path: impl AsRef<Path>) -> io::Result<()> {
+        // an image rendered from this population's history
+        heatmap::write_ppm(image, &path)?;
+        self.metadata().write_sidecar(path)
+    }
+
+    pub fn metadata(&self) -> RunMetadata {
+        let parameter_hash = self.parameter_hash();
+        RunMetadata {
+            run_id: format!("{:016x}-{:016x}", self.seed, parameter_hash),
+            seed: self.seed,
+            parameter_hash,
+        }
+    }
+}
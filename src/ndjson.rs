@@ -0,0 +1,136 @@
+use std::io::{self, Write};
+
+use crate::history::{Checkpoint, History};
+use crate::{EventRecord, Population, SimError, StopReason};
+
+fn json_list<T>(items: impl Iterator<Item = T>, format: impl Fn(T) -> String) -> String {
+    let items: Vec<String> = items.map(format).collect();
+    format!("[{}]", items.join(","))
+}
+
+pub fn checkpoint_json(checkpoint: &Checkpoint) -> String {
+    // One checkpoint as a single-line JSON object
+
+    let names = json_list(checkpoint.names.iter(), |x| {
+        format!("\"{}\"", x.replace('\\', "\\\\").replace('"', "\\\""))
+    });
+    let colors = json_list(checkpoint.colors.iter(), |[r, g, b]| {
+        format!("\"#{r:02x}{g:02x}{b:02x}\"")
+    });
+    format!(
+        "{{\"type\":\"checkpoint\",\"t\":{},\"uids\":{},\"species\":{},\"coords\":{},\"counts\":{},\"names\":{names},\"colors\":{colors},\"traits\":{}}}",
+        checkpoint.t,
+        json_list(checkpoint.uids.iter(), |x| x.to_string()),
+        json_list(checkpoint.species.iter(), |x| x.to_string()),
+        json_list(0..checkpoint.coords.len(), |idx| {
+            let (x, y) = checkpoint.coords.get(idx);
+            format!("[{x},{y}]")
+        }),
+        json_list(checkpoint.counts.iter(), |x| x.to_string()),
+        json_list(checkpoint.traits.iter(), |x| x.to_string()),
+    )
+}
+
+pub fn summary_json(checkpoint: &Checkpoint) -> String {
+    // A checkpoint reduced to its size, the count of each species and each species' mean
+    // trait (null for an absent species), without any coordinates
+
+    let mut totals = vec![0.0; checkpoint.counts.len()];
+    for (species, value) in checkpoint.species.iter().zip(&checkpoint.traits) {
+        totals[*species as usize] += value;
+    }
+    let means = json_list(
+        totals.iter().zip(&checkpoint.counts),
+        |(total, count)| match count {
+            0 => "null".to_string(),
+            _ => (total / *count as f64).to_string(),
+        },
+    );
+    format!(
+        "{{\"type\":\"summary\",\"t\":{},\"size\":{},\"counts\":{},\"mean_traits\":{means}}}",
+        checkpoint.t,
+        checkpoint.uids.len(),
+        json_list(checkpoint.counts.iter(), |x| x.to_string()),
+    )
+}
+
+pub fn event_json(record: &EventRecord) -> String {
+    // One event record as a single-line JSON object, with null for absent fields
+
+    let uid = record
+        .individual_id
+        .map_or("null".to_string(), |x| x.to_string());
+    let position = record
+        .position
+        .map_or("null".to_string(), |(x, y)| format!("[{x},{y}]"));
+    format!(
+        "{{\"type\":\"event\",\"t\":{},\"kind\":\"{:?}\",\"uid\":{uid},\"species\":{},\"position\":{position}}}",
+        record.time, record.kind, record.species_id
+    )
+}
+
+impl History {
+    pub fn to_ndjson(&self, mut writer: impl Write) -> io::Result<()> {
+        for checkpoint in &self.checkpoints {
+            writeln!(writer, "{}", checkpoint_json(checkpoint))?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a> Population<'a> {
+    pub fn stream_ndjson(
+        &mut self,
+        max_t: f64,
+        mut writer: impl Write,
+    ) -> Result<StopReason, SimError> {
+        // Simulate up to max_t as simulate would, writing every event and checkpoint as a
+        // JSON line as it happens; leaps batch many events, so under tau-leaping only the
+        // checkpoints are written
+
+        if self.history.checkpoints.is_empty() {
+            self.record_checkpoint();
+        }
+        let mut written = 0;
+        // the first failed write, reported once the run is over
+        let mut failure: Option<io::Error> = None;
+        let mut write = |population: &Population, record: Option<&EventRecord>| {
+            if failure.is_some() {
+                return;
+            }
+            let checkpoints = &population.history.checkpoints[written..];
+            written = population.history.checkpoints.len();
+            failure = record
+                .map(event_json)
+                .into_iter()
+                .chain(checkpoints.iter().map(checkpoint_json))
+                .try_for_each(|line| writeln!(writer, "{line}"))
+                .err();
+        };
+        write(self, None);
+        let reason = self.simulate_with(max_t, &mut write)?;
+        // output times filled in after an early stop
+        write(self, None);
+        if let Some(e) = failure {
+            return Err(e.into());
+        }
+        writer.flush()?;
+        Ok(reason)
+    }
+
+    pub fn stream_summary_ndjson(
+        &mut self,
+        max_t: f64,
+        mut writer: impl Write,
+    ) -> Result<(), SimError> {
+        // Simulate up to max_t writing a summary line per checkpoint, and the full final
+        // state only once the run is over, for runs too large to stream coordinates
+
+        for checkpoint in self.checkpoints(max_t) {
+            writeln!(writer, "{}", summary_json(&checkpoint?))?;
+        }
+        writeln!(writer, "{}", checkpoint_json(&self.get_checkpoint()))?;
+        writer.flush()?;
+        Ok(())
+    }
+}
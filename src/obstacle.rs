@@ -0,0 +1,35 @@
+#[derive(Clone, Debug)]
+pub enum Obstacle {
+    Rectangle {
+        x_min: f64,
+        y_min: f64,
+        x_max: f64,
+        y_max: f64,
+    },
+    // vertices in order, implicitly closed
+    Polygon(Vec<(f64, f64)>),
+}
+
+impl Obstacle {
+    pub fn contains(&self, x: f64, y: f64) -> bool {
+        match self {
+            Obstacle::Rectangle {
+                x_min,
+                y_min,
+                x_max,
+                y_max,
+            } => *x_min <= x && x < *x_max && *y_min <= y && y < *y_max,
+            Obstacle::Polygon(vertices) => {
+                // even-odd ray casting towards +x
+                let mut inside = false;
+                for (k, (x1, y1)) in vertices.iter().enumerate() {
+                    let (x2, y2) = vertices[(k + 1) % vertices.len()];
+                    if (*y1 > y) != (y2 > y) && x < x1 + (y - y1) * (x2 - x1) / (y2 - y1) {
+                        inside = !inside;
+                    }
+                }
+                inside
+            }
+        }
+    }
+}
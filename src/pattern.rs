@@ -0,0 +1,104 @@
+use std::error;
+use std::fmt;
+
+use rand::Rng;
+
+use crate::sampling::sample_normal;
+
+#[derive(Clone, Debug, Default)]
+pub enum Pattern {
+    #[default]
+    Uniform,
+    // Thomas process: offspring scattered with a gaussian of sd `sd` around `parents`
+    // uniformly placed cluster centres
+    Thomas {
+        parents: usize,
+        sd: f64,
+    },
+    // regular square lattice, one individual per cell centre
+    Grid,
+    // single point release
+    Point(f64, f64),
+    // explicit starting coordinates, overriding the initial count
+    Explicit(Vec<(f64, f64)>),
+}
+
+impl Pattern {
+    pub fn is_random(&self) -> bool {
+        // whether positions are drawn at random, so that a draw can be repeated
+        matches!(self, Pattern::Uniform | Pattern::Thomas { .. })
+    }
+
+    pub fn positions(&self, count: usize, rng: &mut impl Rng) -> Vec<(f64, f64)> {
+        // starting coordinates on the unit torus for `count` individuals
+
+        match self {
+            Pattern::Uniform => (0..count).map(|_| (rng.gen(), rng.gen())).collect(),
+            Pattern::Thomas { parents, sd } => {
+                let centres: Vec<(f64, f64)> = (0..(*parents).max(1))
+                    .map(|_| (rng.gen(), rng.gen()))
+                    .collect();
+                (0..count)
+                    .map(|_| {
+                        let (x, y) = centres[rng.gen_range(0..centres.len())];
+                        (
+                            sample_normal(rng, x, *sd).rem_euclid(1.0),
+                            sample_normal(rng, y, *sd).rem_euclid(1.0),
+                        )
+                    })
+                    .collect()
+            }
+            Pattern::Grid => {
+                let side = (count as f64).sqrt().ceil().max(1.0) as usize;
+                let spacing = 1.0 / side as f64;
+                (0..count)
+                    .map(|k| {
+                        (
+                            ((k % side) as f64 + 0.5) * spacing,
+                            ((k / side) as f64 + 0.5) * spacing,
+                        )
+                    })
+                    .collect()
+            }
+            Pattern::Point(x, y) => vec![(*x, *y); count],
+            Pattern::Explicit(coords) => coords.clone(),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), OutOfDomain> {
+        // fixed starting coordinates must lie in the unit square
+        match self {
+            Pattern::Point(x, y) => check_domain(&[(*x, *y)]),
+            Pattern::Explicit(coords) => check_domain(coords),
+            _ => Ok(()),
+        }
+    }
+}
+
+pub(crate) fn check_domain(coordinates: &[(f64, f64)]) -> Result<(), OutOfDomain> {
+    for (index, &(x, y)) in coordinates.iter().enumerate() {
+        if !(0.0..1.0).contains(&x) || !(0.0..1.0).contains(&y) {
+            return Err(OutOfDomain { index, x, y });
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug)]
+pub struct OutOfDomain {
+    pub index: usize,
+    pub x: f64,
+    pub y: f64,
+}
+
+impl fmt::Display for OutOfDomain {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "coordinate {} at ({}, {}) lies outside the unit square",
+            self.index, self.x, self.y
+        )
+    }
+}
+
+impl error::Error for OutOfDomain {}
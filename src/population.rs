@@ -0,0 +1,1236 @@
+use std::mem;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+#[cfg(feature = "parallel")]
+use std::thread;
+
+use rand::prelude::*;
+
+use crate::analysis::torus_distance;
+use crate::error::SimError;
+use crate::genealogy::Genealogy;
+use crate::grid::Grid;
+use crate::habitat::Habitat;
+use crate::heatmap::Kde;
+use crate::history::{Change, History, Precision, Stationarity};
+use crate::individual::{torus_offset, Health, Individual, Stage};
+use crate::metadata::settings_hash;
+use crate::obstacle::Obstacle;
+use crate::pattern::check_domain;
+use crate::resource::Resource;
+use crate::sampling::{random_sex, sample_normal, sample_poisson, CompensatedSum, RateTree};
+use crate::schedule::{Harvest, Introduction};
+use crate::species::Species;
+use crate::streams::{Stream, Streams};
+use crate::watch::Watcher;
+use crate::{genetics, kernels, SimulationConfig};
+
+// number of placements tried before a birth, move, or arrival is abandoned
+pub(crate) const PLACEMENT_ATTEMPTS: usize = 10;
+
+// distance counted between an individual and itself, beyond any kernel radius that fits
+// on the unit torus
+pub(crate) const DIAGONAL: f64 = 1.0;
+
+// fewest individuals whose refresh the parallel feature splits over threads
+#[cfg(feature = "parallel")]
+const PARALLEL_MIN: usize = 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Event {
+    Birth,
+    Death,
+    Move,
+    Immigration,
+    Maturation,
+    Infection,
+    Recovery,
+    DiseaseDeath,
+    Harvest,
+    Introduction,
+}
+
+// what a single step did: for individual events the subject's uid, species and position
+// afterwards (a parent for births, the arrival for immigration); harvests and
+// introductions only name the species
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EventRecord {
+    pub time: f64,
+    pub kind: Event,
+    pub individual_id: Option<usize>,
+    pub species_id: u8,
+    pub position: Option<(f64, f64)>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CapPolicy {
+    // halt the simulation once the cap is reached
+    Stop,
+    // suppress births and arrivals while at the cap
+    Throttle,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Stepping {
+    // one event at a time, reproducing the exact process
+    Exact,
+    // batch the events of a time leap sized so that about epsilon of the population is
+    // expected to change per leap; only the runners that report checkpoints rather than
+    // individual events leap
+    TauLeap { epsilon: f64 },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StopReason {
+    MaxTime,
+    Extinct,
+    SizeCap,
+    Stationary,
+    Cancelled,
+}
+
+pub struct Population<'a> {
+    pub(crate) species: Vec<&'a Species>,
+    pub(crate) individuals: Vec<Individual<'a>>,
+    pub(crate) size: usize,
+    // everyone bucketed by position, for finding who is within interacting distance
+    pub(crate) grid: Grid,
+    // each individual's total event rate, refreshed for the individuals listed in stale
+    // (or for everyone when all_stale is set) before the next event is drawn
+    pub(crate) rates: RateTree,
+    pub(crate) stale: Vec<usize>,
+    pub(crate) all_stale: bool,
+    // whether births were throttled when the rates were last refreshed in full
+    pub(crate) rates_throttled: bool,
+    pub(crate) t: f64,
+    pub(crate) seed: u64,
+    pub(crate) streams: Streams,
+    pub(crate) birth_habitat: Option<Habitat>,
+    pub(crate) death_habitat: Option<Habitat>,
+    pub(crate) resource: Option<Resource>,
+    pub(crate) obstacles: Vec<Obstacle>,
+    pub(crate) size_cap: Option<(usize, CapPolicy)>,
+    pub(crate) stepping: Stepping,
+    pub(crate) stationarity: Option<Stationarity>,
+    pub(crate) harvests: Vec<Harvest>,
+    pub(crate) introductions: Vec<Introduction<'a>>,
+    pub(crate) history: History,
+    pub(crate) burn_in: f64,
+    pub(crate) checkpoint_interval: f64,
+    // ascending times still to be checkpointed, replacing per-event checkpoints
+    pub(crate) output_times: Option<Vec<f64>>,
+    pub(crate) watchers: Vec<Watcher<'a>>,
+    pub(crate) genealogy: Genealogy,
+    // set from another thread to stop a run between steps, keeping what it recorded
+    pub(crate) cancel: Option<Arc<AtomicBool>>,
+    // hash of the configuration the population was built from, less its seed
+    pub(crate) settings_hash: u64,
+}
+
+impl<'a> Population<'a> {
+    pub fn new(species_list: Vec<&'a Species>) -> Result<Self, SimError> {
+        Population::from_seed(species_list, rand::thread_rng().gen())
+    }
+
+    pub fn from_seed(species_list: Vec<&'a Species>, seed: u64) -> Result<Self, SimError> {
+        Population::with_obstacles(species_list, seed, vec![])
+    }
+
+    pub fn with_obstacles(
+        species_list: Vec<&'a Species>,
+        seed: u64,
+        obstacles: Vec<Obstacle>,
+    ) -> Result<Self, SimError> {
+        // Place the initial individuals of each species according to its pattern outside
+        // the obstacles. Random patterns redraw the positions that land inside one; fixed
+        // positions inside one are an error
+
+        for species in &species_list {
+            species.validate()?;
+        }
+
+        let blocked = |(x, y): (f64, f64)| obstacles.iter().any(|o| o.contains(x, y));
+        let mut streams = Streams::new(seed);
+        let mut placements = vec![];
+        for species in &species_list {
+            let rng = streams.get(Stream::Initial);
+            let mut positions = species.Init.positions(species.C1 as usize, rng);
+            if let Some(&(x, y)) = positions.iter().find(|p| blocked(**p)) {
+                let obstructed = SimError::Obstructed {
+                    species: species.id,
+                    x,
+                    y,
+                };
+                if !species.Init.is_random() {
+                    return Err(obstructed);
+                }
+                let count = positions.len();
+                positions.retain(|p| !blocked(*p));
+                for _ in 0..PLACEMENT_ATTEMPTS {
+                    if positions.len() == count {
+                        break;
+                    }
+                    let redrawn = species.Init.positions(count - positions.len(), rng);
+                    positions.extend(redrawn.into_iter().filter(|p| !blocked(*p)));
+                }
+                if positions.len() < count {
+                    return Err(obstructed);
+                }
+            }
+            placements.extend(positions.into_iter().map(|p| (*species, p)));
+        }
+        let mut population = Population::from_placements(species_list, placements, seed, streams);
+        population.obstacles = obstacles;
+        Ok(population)
+    }
+
+    pub fn from_coordinates(
+        species: &'a Species,
+        coordinates: Vec<(f64, f64)>,
+    ) -> Result<Self, SimError> {
+        // start from observed positions, which must lie in the unit square
+
+        species.validate()?;
+        check_domain(&coordinates)?;
+        let seed = rand::thread_rng().gen();
+        let placements = coordinates.into_iter().map(|p| (species, p)).collect();
+        Ok(Population::from_placements(
+            vec![species],
+            placements,
+            seed,
+            Streams::new(seed),
+        ))
+    }
+
+    pub(crate) fn from_placements(
+        species_list: Vec<&'a Species>,
+        placements: Vec<(&'a Species, (f64, f64))>,
+        seed: u64,
+        mut streams: Streams,
+    ) -> Self {
+        let rng = streams.get(Stream::Initial);
+        // create individuals at the given positions
+        let mut individuals: Vec<Individual> = vec![];
+        let mut idx = 0;
+        let mut genealogy = Genealogy::new();
+        for (species, (x, y)) in placements {
+            let mut new_individual = Individual::new(idx, species, x, y);
+            new_individual.sex = random_sex(rng);
+            new_individual.genome = genetics::random_genome(species, rng);
+            new_individual.uid = genealogy.record_birth(None, species.id, 0.0);
+            if rng.gen::<f64>() < species.Eprev {
+                new_individual.health = Health::Infected;
+            }
+            individuals.push(new_individual);
+            idx += 1;
+        }
+
+        // instantiate population
+        let settings_hash =
+            settings_hash(&SimulationConfig::new(species_list.clone(), f64::INFINITY));
+        let mut population = Population {
+            species: species_list,
+            individuals,
+            size: idx,
+            grid: Grid::default(),
+            rates: RateTree::default(),
+            stale: vec![],
+            all_stale: true,
+            rates_throttled: false,
+            t: 0.0,
+            seed,
+            streams,
+            birth_habitat: None,
+            death_habitat: None,
+            resource: None,
+            obstacles: vec![],
+            size_cap: None,
+            stepping: Stepping::Exact,
+            stationarity: None,
+            harvests: vec![],
+            introductions: vec![],
+            history: History::new(Precision::Double),
+            burn_in: 0.0,
+            checkpoint_interval: 0.0,
+            output_times: None,
+            watchers: vec![],
+            genealogy,
+            cancel: None,
+            settings_hash,
+        };
+        population.rebuild_grid();
+        population.refresh_neighborhoods();
+        population
+    }
+
+    pub fn t(&self) -> f64 {
+        self.t
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn species(&self) -> &[&'a Species] {
+        &self.species
+    }
+
+    pub fn individuals(&self) -> &[Individual<'a>] {
+        &self.individuals
+    }
+
+    pub fn history(&self) -> &History {
+        &self.history
+    }
+
+    pub fn genealogy(&self) -> &Genealogy {
+        &self.genealogy
+    }
+
+    pub fn set_habitat(&mut self, birth: Option<Habitat>, death: Option<Habitat>) {
+        // scale each individual's birth and death rates by the quality of the cell it is
+        // in; None leaves that rate unscaled
+        self.birth_habitat = birth;
+        self.death_habitat = death;
+        self.all_stale = true;
+    }
+
+    pub fn set_resource(&mut self, resource: Option<Resource>) {
+        // scale birth rates by the local level of a resource the population consumes
+        self.resource = resource;
+        self.all_stale = true;
+    }
+
+    pub fn set_event_log(&mut self, enabled: bool) {
+        // Record the first checkpoint and then a log of every change instead of a
+        // checkpoint per event. This starts a new history, so it belongs before the run
+
+        let precision = self.history.precision;
+        let heatmap = self.history.heatmap;
+        self.history = if enabled {
+            History::with_event_log(precision)
+        } else {
+            History::new(precision)
+        };
+        self.history.heatmap = heatmap;
+    }
+
+    pub fn set_heatmap(&mut self, kde: Option<Kde>) {
+        // attach a kernel density estimate to every checkpoint recorded from now on
+        self.history.heatmap = kde;
+    }
+
+    pub fn add_obstacle(&mut self, obstacle: Obstacle) {
+        // block births, moves and arrivals into a region from now on; individuals already
+        // inside it stay where they are
+        self.obstacles.push(obstacle);
+    }
+
+    pub(crate) fn rebuild_grid(&mut self) {
+        // bucket everyone afresh in a grid whose cells span the largest interaction
+        // radius of the species taking part
+        let radius = self
+            .species
+            .iter()
+            .map(|x| x.interaction_radius())
+            .fold(0.0, f64::max);
+        let positions = self.individuals.iter().map(|x| (x.x_coord, x.y_coord));
+        self.grid = Grid::new(radius, positions);
+    }
+
+    fn distance(&self, i: usize, j: usize) -> f64 {
+        // torus distance between two individuals, DIAGONAL for one and itself
+        if i == j {
+            DIAGONAL
+        } else {
+            self.individuals[i].distance(&self.individuals[j])
+        }
+    }
+
+    pub(crate) fn habitat_quality(&self, x: f64, y: f64) -> (f64, f64) {
+        // the factors scaling birth and death rates at a point
+        (
+            self.birth_habitat.as_ref().map_or(1.0, |h| h.at(x, y))
+                * self.resource.as_ref().map_or(1.0, |r| r.fraction_at(x, y)),
+            self.death_habitat.as_ref().map_or(1.0, |h| h.at(x, y)),
+        )
+    }
+
+    fn refresh_rates(&mut self, idx: usize) -> Result<f64, SimError> {
+        // recompute one individual's event rates from its neighborhood and position,
+        // returning their total
+        let (x, y) = (self.individuals[idx].x_coord, self.individuals[idx].y_coord);
+        let quality = self.habitat_quality(x, y);
+        individual_rates(&mut self.individuals[idx], quality, self.rates_throttled)
+    }
+
+    pub(crate) fn update_rates(&mut self) -> Result<(), SimError> {
+        // Bring the event rates up to date before the next event is drawn: only those of
+        // the individuals whose neighborhood or state changed since the last update, or
+        // everyone's when something they all depend on did (a habitat, the resource, or
+        // the size cap starting or ending a throttle)
+
+        let throttled = self.throttled();
+        if self.all_stale || throttled != self.rates_throttled {
+            self.all_stale = false;
+            self.stale.clear();
+            self.rates_throttled = throttled;
+            let qualities: Vec<(f64, f64)> = self
+                .individuals
+                .iter()
+                .map(|x| self.habitat_quality(x.x_coord, x.y_coord))
+                .collect();
+            let rates = for_each_individual(&mut self.individuals, |idx, individual| {
+                individual_rates(individual, qualities[idx], throttled)
+            });
+            let rates: Vec<f64> = rates.into_iter().collect::<Result<_, _>>()?;
+            self.rates = RateTree::from_rates(rates);
+            return Ok(());
+        }
+
+        let mut stale = mem::take(&mut self.stale);
+        stale.sort_unstable();
+        stale.dedup();
+        for idx in stale {
+            let rate = self.refresh_rates(idx)?;
+            self.rates.set(idx, rate);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn throttled(&self) -> bool {
+        // whether a throttling size cap is currently holding back growth
+        matches!(self.size_cap, Some((limit, CapPolicy::Throttle)) if self.size >= limit)
+    }
+
+    pub(crate) fn immigration_rate(&self, species: &Species) -> f64 {
+        if self.throttled() {
+            0.0
+        } else {
+            species.I0
+        }
+    }
+
+    fn mark_stale(&mut self, idx: usize, distance: f64) {
+        // queue a neighbor's rates for an update if the change at this distance reached it
+        if distance < self.grid.radius() {
+            self.stale.push(idx);
+        }
+    }
+
+    pub(crate) fn add_individual(&mut self, mut individual: Individual<'a>) {
+        // append an individual, counting it into the densities and infection pressure of
+        // those near it and they into its own
+
+        let (x, y) = (individual.x_coord, individual.y_coord);
+        let idx = self.size;
+        individual.add_neighbor(DIAGONAL, 1.0);
+        for j in self.grid.near(x, y) {
+            let other = &mut self.individuals[j];
+            let d = individual.distance(other);
+            other.add_neighbor(d, 1.0);
+            individual.add_neighbor(d, 1.0);
+            if individual.health == Health::Infected {
+                other.add_infected_neighbor(d, 1.0);
+            }
+            if other.health == Health::Infected {
+                individual.add_infected_neighbor(d, 1.0);
+            }
+            self.mark_stale(j, d);
+        }
+        self.grid.insert(idx, x, y);
+        self.rates.push(0.0);
+        self.stale.push(idx);
+
+        let change = Change::Added {
+            species: individual.species.id,
+            x,
+            y,
+        };
+        self.log_change(individual.uid, change);
+        self.individuals.push(individual);
+        self.size += 1;
+    }
+
+    pub(crate) fn blocked(&self, x_coord: f64, y_coord: f64) -> bool {
+        // whether a point falls inside any obstacle
+        self.obstacles.iter().any(|x| x.contains(x_coord, y_coord))
+    }
+
+    fn has_mate(&self, parent: usize) -> bool {
+        // whether a conspecific of the opposite sex lives within the mating radius
+
+        let parent = &self.individuals[parent];
+        let species = parent.species;
+        species.Rmate <= 0.0
+            || self
+                .grid
+                .near(parent.x_coord, parent.y_coord)
+                .into_iter()
+                .map(|j| &self.individuals[j])
+                .any(|other| {
+                    other.species.id == species.id
+                        && other.sex != parent.sex
+                        && parent.distance(other) < species.Rmate
+                })
+    }
+
+    fn execute_birth(&mut self, parent: usize) {
+        // produce a clutch of offspring, each placed independently around the parent;
+        // two-sex species need a mate nearby or the birth fails
+
+        if !self.has_mate(parent) {
+            return;
+        }
+        let species = self.individuals[parent].species;
+        let clutch = 1 + sample_poisson(
+            self.streams.get(Stream::Birth),
+            (species.Clutch - 1.0).max(0.0),
+        );
+        for _ in 0..clutch {
+            self.place_offspring(parent);
+        }
+    }
+
+    fn place_offspring(&mut self, parent: usize) {
+        // create a new individual near its parent, resampling placements that fall inside
+        // an obstacle or the exclusion radius of an existing individual
+
+        let species = self.individuals[parent].species;
+        let x_parent = self.individuals[parent].x_coord;
+        let y_parent = self.individuals[parent].y_coord;
+        let trait_parent = self.individuals[parent].trait_value;
+        let genome = genetics::inherit(
+            &self.individuals[parent].genome,
+            species,
+            self.streams.get(Stream::Birth),
+        );
+
+        for _ in 0..PLACEMENT_ATTEMPTS {
+            let (dx, dy) = kernels::sample_truncated_gaussian_offset(
+                self.streams.get(Stream::Birth),
+                species.Mbsd,
+                species.Mbrmax,
+            );
+            let mut child = Individual::new(
+                self.size,
+                species,
+                (x_parent + dx).rem_euclid(1.0),
+                (y_parent + dy).rem_euclid(1.0),
+            );
+            if !self.blocked(child.x_coord, child.y_coord)
+                && self
+                    .grid
+                    .near(child.x_coord, child.y_coord)
+                    .into_iter()
+                    .all(|j| {
+                        child.distance(&self.individuals[j]) >= self.individuals[j].species.Rexcl
+                    })
+            {
+                if species.G0 > 0.0 {
+                    child.stage = Stage::Juvenile;
+                }
+                let rng = self.streams.get(Stream::Birth);
+                child.sex = random_sex(rng);
+                child.trait_value = sample_normal(rng, trait_parent, species.Tmut);
+                child.birth_time = self.t;
+                child.genome = genome;
+                child.uid = self.genealogy.record_birth(
+                    Some(self.individuals[parent].uid),
+                    species.id,
+                    self.t,
+                );
+                self.add_individual(child);
+                return;
+            }
+        }
+    }
+
+    pub(crate) fn execute_death(&mut self, idx: usize) {
+        // Remove an individual from the population and from its neighbors' densities and
+        // infection pressure. The last individual takes its place, so that no other
+        // index changes
+
+        let deceased = &self.individuals[idx];
+        let (x, y) = (deceased.x_coord, deceased.y_coord);
+        let infected = deceased.health == Health::Infected;
+        for j in self.grid.near(x, y).into_iter().filter(|j| *j != idx) {
+            let d = self.distance(idx, j);
+            let other = &mut self.individuals[j];
+            other.add_neighbor(d, -1.0);
+            if infected {
+                other.add_infected_neighbor(d, -1.0);
+            }
+            self.mark_stale(j, d);
+        }
+
+        let last = self.size - 1;
+        self.grid.remove(idx, x, y);
+        if idx < last {
+            let (x, y) = (
+                self.individuals[last].x_coord,
+                self.individuals[last].y_coord,
+            );
+            self.grid.relabel(last, idx, x, y);
+        }
+        let deceased = self.individuals.swap_remove(idx);
+        if idx < last {
+            self.individuals[idx].id = idx;
+        }
+        self.rates.swap_remove(idx);
+        self.stale.retain(|x| *x != idx);
+        for stale in self.stale.iter_mut().filter(|x| **x == last) {
+            *stale = idx;
+        }
+        self.size -= 1;
+
+        self.genealogy.record_death(deceased.uid, self.t);
+        self.log_change(deceased.uid, Change::Removed);
+    }
+
+    fn taxis_bias(&self, idx: usize) -> (f64, f64) {
+        // drift of a move step: Xcon steps of size Mdsd up the conspecific density
+        // gradient plus Xhet steps down the heterospecific one, with both gradients taken
+        // under the movement kernel (Mrmax, Msd)
+
+        let individual = &self.individuals[idx];
+        let species = individual.species;
+        if species.Xcon == 0.0 && species.Xhet == 0.0 {
+            return (0.0, 0.0);
+        }
+
+        let var = species.Msd.powi(2);
+        let mut conspecific = (0.0, 0.0);
+        let mut heterospecific = (0.0, 0.0);
+        for j in self.grid.near(individual.x_coord, individual.y_coord) {
+            let other = &self.individuals[j];
+            let d = individual.distance(other);
+            if j == idx || var == 0.0 || d >= species.Mrmax {
+                continue;
+            }
+            let w = (-d.powi(2) / (2.0 * var)).exp();
+            let dx = w * torus_offset(individual.x_coord, other.x_coord);
+            let dy = w * torus_offset(individual.y_coord, other.y_coord);
+            let gradient = if other.species.id == species.id {
+                &mut conspecific
+            } else {
+                &mut heterospecific
+            };
+            gradient.0 += dx;
+            gradient.1 += dy;
+        }
+
+        let unit = |(x, y): (f64, f64)| {
+            let length = (x * x + y * y).sqrt();
+            if length > 0.0 {
+                (x / length, y / length)
+            } else {
+                (0.0, 0.0)
+            }
+        };
+        let (cx, cy) = unit(conspecific);
+        let (hx, hy) = unit(heterospecific);
+        (
+            species.Mdsd * (species.Xcon * cx - species.Xhet * hx),
+            species.Mdsd * (species.Xcon * cy - species.Xhet * hy),
+        )
+    }
+
+    fn execute_move(&mut self, idx: usize) {
+        // move an individual within the population, either as a free random walk or, for
+        // species with a home range, as an Ornstein-Uhlenbeck step tethered to its anchor
+        // whose stationary spread is Hrange
+
+        let (x_bias, y_bias) = self.taxis_bias(idx);
+        let individual = &self.individuals[idx];
+        let sd = individual.species.Mdsd;
+        let range = individual.species.Hrange;
+        let (x_mean, y_mean, spread) = if range > 0.0 {
+            let pull = (1.0 - (sd / range).powi(2)).max(0.0).sqrt();
+            let (x_anchor, y_anchor) = individual.anchor;
+            (
+                x_anchor + pull * torus_offset(x_anchor, individual.x_coord) + x_bias,
+                y_anchor + pull * torus_offset(y_anchor, individual.y_coord) + y_bias,
+                (1.0 - pull.powi(2)).sqrt() * range,
+            )
+        } else {
+            (individual.x_coord + x_bias, individual.y_coord + y_bias, sd)
+        };
+
+        // steps into an obstacle are redrawn, and the individual stays put if none succeed
+        for _ in 0..PLACEMENT_ATTEMPTS {
+            let rng = self.streams.get(Stream::Movement);
+            let x_coord = sample_normal(rng, x_mean, spread).rem_euclid(1.0);
+            let y_coord = sample_normal(rng, y_mean, spread).rem_euclid(1.0);
+            if self.blocked(x_coord, y_coord) {
+                continue;
+            }
+
+            self.relocate(idx, x_coord, y_coord);
+            return;
+        }
+    }
+
+    fn relocate(&mut self, idx: usize, x_coord: f64, y_coord: f64) {
+        // Move an individual to a new position: its neighbors near either end of the
+        // move have it shifted in their densities, while its own densities and infection
+        // pressure are recounted at the new position
+
+        let (x_old, y_old) = (self.individuals[idx].x_coord, self.individuals[idx].y_coord);
+        let mut affected = self.grid.near(x_old, y_old);
+        affected.extend(self.grid.near(x_coord, y_coord));
+        affected.sort_unstable();
+        affected.dedup();
+        self.grid.remove(idx, x_old, y_old);
+        self.grid.insert(idx, x_coord, y_coord);
+
+        let infected = self.individuals[idx].health == Health::Infected;
+        for j in affected.into_iter().filter(|j| *j != idx) {
+            let other = &mut self.individuals[j];
+            let before = torus_distance((x_old, y_old), (other.x_coord, other.y_coord));
+            let d = torus_distance((x_coord, y_coord), (other.x_coord, other.y_coord));
+            other.add_neighbor(before, -1.0);
+            other.add_neighbor(d, 1.0);
+            if infected {
+                other.add_infected_neighbor(before, -1.0);
+                other.add_infected_neighbor(d, 1.0);
+            }
+            self.mark_stale(j, before.min(d));
+        }
+
+        let mover = &mut self.individuals[idx];
+        mover.x_coord = x_coord;
+        mover.y_coord = y_coord;
+        let uid = mover.uid;
+        self.log_change(
+            uid,
+            Change::Moved {
+                x: x_coord,
+                y: y_coord,
+            },
+        );
+        self.refresh_neighborhood(idx);
+        self.stale.push(idx);
+    }
+
+    pub(crate) fn refresh_neighborhood(&mut self, idx: usize) {
+        // recount one individual's densities and infection pressure from those near it,
+        // in index order; an individual is DIAGONAL from itself, so it only counts
+        // itself if a kernel reaches that far
+
+        let individual = &self.individuals[idx];
+        let near: Vec<(f64, bool)> = self
+            .grid
+            .near(individual.x_coord, individual.y_coord)
+            .into_iter()
+            .map(|j| {
+                let infected = j != idx && self.individuals[j].health == Health::Infected;
+                (self.distance(idx, j), infected)
+            })
+            .collect();
+        count_neighborhood(&mut self.individuals[idx], near);
+    }
+
+    fn refresh_neighborhoods(&mut self) {
+        // refresh_neighborhood for everyone, from a copy of where each individual is and
+        // whether it is infected so that the individuals can be recounted independently
+
+        let places: Vec<(f64, f64, bool)> = self
+            .individuals
+            .iter()
+            .map(|x| (x.x_coord, x.y_coord, x.health == Health::Infected))
+            .collect();
+        let grid = &self.grid;
+        for_each_individual(&mut self.individuals, |idx, individual| {
+            let (x, y, _) = places[idx];
+            let near = grid.near(x, y).into_iter().map(|j| {
+                let (other_x, other_y, infected) = places[j];
+                if j == idx {
+                    (DIAGONAL, false)
+                } else {
+                    (torus_distance((x, y), (other_x, other_y)), infected)
+                }
+            });
+            count_neighborhood(individual, near);
+        });
+    }
+
+    fn execute_immigration(&mut self, species_idx: usize) {
+        // add an individual arriving from outside, either anywhere in the domain or along
+        // the x = 0 edge
+
+        let species = self.species[species_idx];
+        let mut arrival = None;
+        for _ in 0..PLACEMENT_ATTEMPTS {
+            let rng = self.streams.get(Stream::Immigration);
+            let x_coord = if species.Iedge { 0.0 } else { rng.gen() };
+            let y_coord = rng.gen();
+            if !self.blocked(x_coord, y_coord) {
+                arrival = Some((x_coord, y_coord));
+                break;
+            }
+        }
+        let Some((x_coord, y_coord)) = arrival else {
+            return;
+        };
+        let immigrant = self.new_arrival(species, x_coord, y_coord);
+        self.add_individual(immigrant);
+    }
+
+    pub(crate) fn new_arrival(
+        &mut self,
+        species: &'a Species,
+        x_coord: f64,
+        y_coord: f64,
+    ) -> Individual<'a> {
+        // an individual with no parent in the population, arriving now
+
+        let mut arrival = Individual::new(self.size, species, x_coord, y_coord);
+        let rng = self.streams.get(Stream::Immigration);
+        arrival.sex = random_sex(rng);
+        arrival.birth_time = self.t;
+        arrival.genome = genetics::random_genome(species, rng);
+        arrival.uid = self.genealogy.record_birth(None, species.id, self.t);
+        arrival
+    }
+
+    fn execute_maturation(&mut self, idx: usize) {
+        // promote a juvenile to adulthood
+        self.individuals[idx].stage = Stage::Adult;
+        self.stale.push(idx);
+    }
+
+    fn set_health(&mut self, idx: usize, health: Health) {
+        // change an individual's health, adding it to or taking it out of the infection
+        // pressure of those near it when it starts or stops being infectious
+
+        let individual = &self.individuals[idx];
+        let was_infected = individual.health == Health::Infected;
+        let sign = match (was_infected, health == Health::Infected) {
+            (false, true) => 1.0,
+            (true, false) => -1.0,
+            _ => 0.0,
+        };
+        if sign != 0.0 {
+            for j in self.grid.near(individual.x_coord, individual.y_coord) {
+                if j != idx {
+                    let d = self.distance(idx, j);
+                    self.individuals[j].add_infected_neighbor(d, sign);
+                    self.mark_stale(j, d);
+                }
+            }
+        }
+        self.individuals[idx].health = health;
+        self.stale.push(idx);
+    }
+
+    fn execute_infection(&mut self, idx: usize) {
+        self.set_health(idx, Health::Infected);
+    }
+
+    fn execute_recovery(&mut self, idx: usize) {
+        // clear an infection, with lasting immunity under SIR and none under SIS
+        let health = if self.individuals[idx].species.Eimmune {
+            Health::Recovered
+        } else {
+            Health::Susceptible
+        };
+        self.set_health(idx, health);
+    }
+
+    pub(crate) fn total_rate(&self) -> f64 {
+        // sum of every individual event rate, as last updated, and the immigration rates
+
+        let mut total = CompensatedSum::default();
+        total.add(self.rates.total());
+        for species in &self.species {
+            total.add(self.immigration_rate(species));
+        }
+        total.value()
+    }
+
+    fn choose_event(&mut self) -> Option<(Event, usize, f64)> {
+        // pick the event type and individual at random from the population, along with
+        // the waiting time until it happens; immigration events index into the species
+        // list instead
+
+        let total = self.total_rate();
+        if total <= 0.0 {
+            return None;
+        }
+        let delta_t = -(1.0 - self.streams.get(Stream::Events).gen::<f64>()).ln() / total;
+
+        // find the individual the target falls in from the rate tree, then its event
+        // by walking that individual's rates; past every individual, walk the
+        // immigration rates with the compensated running sum
+        let target = self.streams.get(Stream::Events).gen::<f64>() * total;
+        let individuals = self.rates.total();
+        let mut chosen = None;
+        if target < individuals {
+            if let Some((idx, remaining)) = self.rates.find(target) {
+                let mut cumulative = 0.0;
+                for (event, p) in self.individuals[idx].event_rates() {
+                    if p <= 0.0 {
+                        continue;
+                    }
+                    chosen = Some((event, idx, delta_t));
+                    cumulative += p;
+                    if remaining < cumulative {
+                        break;
+                    }
+                }
+                return chosen;
+            }
+        }
+        let mut cumulative = CompensatedSum::default();
+        cumulative.add(individuals);
+        for (idx, species) in self.species.iter().enumerate() {
+            let rate = self.immigration_rate(species);
+            if rate <= 0.0 {
+                continue;
+            }
+            chosen = Some((Event::Immigration, idx, delta_t));
+            cumulative.add(rate);
+            if target < cumulative.value() {
+                return chosen;
+            }
+        }
+        // rounding can leave a sliver of target past the last rate
+        chosen
+    }
+
+    pub(crate) fn next_expiry(&self) -> Option<(usize, f64)> {
+        // the individual due to die of old age first, and when
+
+        if self.species.iter().all(|x| x.Lmax <= 0.0) {
+            return None;
+        }
+        self.individuals
+            .iter()
+            .enumerate()
+            .filter(|(_, x)| x.species.Lmax > 0.0)
+            .map(|(idx, x)| (idx, x.birth_time + x.species.Lmax))
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+    }
+
+    pub(crate) fn next_event(&mut self) -> Option<(Event, usize, f64)> {
+        // the next stochastic event, unless a scheduled death, harvest, or introduction
+        // falls before it; redrawing the stochastic event afterwards is valid because
+        // waiting times are memoryless
+
+        let stochastic = self.choose_event();
+        let scheduled = [
+            self.next_expiry().map(|(idx, at)| (Event::Death, idx, at)),
+            self.next_harvest()
+                .map(|(idx, at)| (Event::Harvest, idx, at)),
+            self.next_introduction()
+                .map(|(idx, at)| (Event::Introduction, idx, at)),
+        ]
+        .into_iter()
+        .flatten()
+        .min_by(|a, b| a.2.total_cmp(&b.2));
+
+        match scheduled {
+            Some((event, idx, at))
+                if stochastic.is_none_or(|(_, _, delta_t)| self.t + delta_t > at) =>
+            {
+                Some((event, idx, (at - self.t).max(0.0)))
+            }
+            _ => stochastic,
+        }
+    }
+
+    pub(crate) fn execute(&mut self, event: Event, idx: usize) {
+        match event {
+            Event::Birth => self.execute_birth(idx),
+            Event::Death => self.execute_death(idx),
+            Event::Move => self.execute_move(idx),
+            Event::Immigration => self.execute_immigration(idx),
+            Event::Maturation => self.execute_maturation(idx),
+            Event::Infection => self.execute_infection(idx),
+            Event::Recovery => self.execute_recovery(idx),
+            Event::DiseaseDeath => self.execute_death(idx),
+            Event::Harvest => self.execute_harvest(idx),
+            Event::Introduction => self.execute_introduction(idx),
+        }
+    }
+
+    pub(crate) fn advance(&mut self, delta_t: f64) {
+        // move the clock forward to the next event, updating the resource field over the
+        // interval during which the population was unchanged
+
+        if let Some(resource) = self.resource.as_mut() {
+            let positions = self.individuals.iter().map(|x| (x.x_coord, x.y_coord));
+            resource.update(positions, delta_t);
+        }
+        self.record_output_times(self.t + delta_t);
+        self.t += delta_t;
+        // the resource level, and with it every birth rate, has changed
+        if self.resource.is_some() {
+            self.all_stale = true;
+        }
+    }
+
+    pub(crate) fn check_rates(&self) -> Result<(), SimError> {
+        // reject rates that would corrupt event selection
+
+        for individual in &self.individuals {
+            for (event, rate) in individual.event_rates() {
+                if !rate.is_finite() || rate < 0.0 {
+                    return Err(SimError::InvalidRate {
+                        uid: individual.uid,
+                        event,
+                        rate,
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn step(&mut self) -> Result<Option<EventRecord>, SimError> {
+        // advance the population by a single event and report it, or None once nothing
+        // can happen
+        self.step_within(f64::INFINITY)
+    }
+
+    pub(crate) fn step_within(&mut self, max_t: f64) -> Result<Option<EventRecord>, SimError> {
+        // as step, but an event that would fall at or after max_t is not carried out: the
+        // clock stops at max_t instead and None is returned
+
+        self.update_rates()?;
+        let Some((event, idx, delta_t)) = self.next_event() else {
+            return Ok(None);
+        };
+        if self.t + delta_t >= max_t {
+            self.advance(max_t - self.t);
+            return Ok(None);
+        }
+        let species_id = match event {
+            Event::Immigration => self.species[idx].id,
+            Event::Harvest => self.harvests[idx].species,
+            Event::Introduction => self.introductions[idx].species.id,
+            _ => self.individuals[idx].species.id,
+        };
+        let subject = match event {
+            Event::Immigration | Event::Harvest | Event::Introduction => None,
+            _ => Some(&self.individuals[idx]),
+        }
+        .map(|x| (x.uid, (x.x_coord, x.y_coord)));
+        let size = self.size;
+
+        self.advance(delta_t);
+        self.execute(event, idx);
+        // leave the rates current, for validate and for anyone inspecting them
+        self.update_rates()?;
+
+        let subject = match event {
+            Event::Move => {
+                let mover = &self.individuals[idx];
+                Some((mover.uid, (mover.x_coord, mover.y_coord)))
+            }
+            Event::Immigration if self.size > size => self
+                .individuals
+                .last()
+                .map(|x| (x.uid, (x.x_coord, x.y_coord))),
+            _ => subject,
+        };
+        // the O(n^2) recomputation of the bookkeeping only runs in debug builds
+        #[cfg(debug_assertions)]
+        if let Err(e) = self.validate() {
+            panic!("after {event:?} at t = {}: {e}", self.t);
+        }
+        Ok(Some(EventRecord {
+            time: self.t,
+            kind: event,
+            individual_id: subject.map(|x| x.0),
+            species_id,
+            position: subject.map(|x| x.1),
+        }))
+    }
+
+    pub fn simulate(&mut self, max_t: f64) -> Result<StopReason, SimError> {
+        // simulate the behaviour of the population over time, reporting why it stopped
+
+        self.simulate_with(max_t, |_, _| ())
+    }
+
+    pub fn cancel_flag(&mut self) -> Arc<AtomicBool> {
+        // A flag that, once set, makes simulate (and the checkpoint iterator) stop before
+        // the next step with StopReason::Cancelled; clear it to run on
+
+        self.cancel
+            .get_or_insert_with(|| Arc::new(AtomicBool::new(false)))
+            .clone()
+    }
+
+    pub(crate) fn early_stop(&self, next_check: &mut f64) -> Option<StopReason> {
+        // why a run should stop before its final time, if it should; stationarity is
+        // only rechecked every tenth of a window, from next_check on
+
+        if self
+            .cancel
+            .as_ref()
+            .is_some_and(|x| x.load(Ordering::Relaxed))
+        {
+            return Some(StopReason::Cancelled);
+        }
+        if matches!(self.size_cap, Some((limit, CapPolicy::Stop)) if self.size >= limit) {
+            return Some(StopReason::SizeCap);
+        }
+        if let Some(detector) = self.stationarity {
+            if self.t >= *next_check {
+                if self.history.stationary_at(&detector, self.t) {
+                    return Some(StopReason::Stationary);
+                }
+                *next_check = self.t + detector.window / 10.0;
+            }
+        }
+        None
+    }
+
+    pub fn simulate_with(
+        &mut self,
+        max_t: f64,
+        mut observe: impl FnMut(&Population, Option<&EventRecord>),
+    ) -> Result<StopReason, SimError> {
+        // as simulate, handing the population to observe after every step along with the
+        // event it carried out; leaps batch many events, so they report none
+
+        if self.history.checkpoints.is_empty() {
+            self.record_checkpoint();
+        }
+        let mut next_check = self.t;
+        let reason = loop {
+            if self.t >= max_t {
+                break StopReason::MaxTime;
+            }
+            if let Some(reason) = self.early_stop(&mut next_check) {
+                break reason;
+            }
+            let record = if self.stepping == Stepping::Exact {
+                match self.step_within(max_t)? {
+                    Some(record) => Some(record),
+                    None if self.t >= max_t => break StopReason::MaxTime,
+                    None => break StopReason::Extinct,
+                }
+            } else if self.progress(max_t)? {
+                None
+            } else {
+                break StopReason::Extinct;
+            };
+            self.record_checkpoint();
+            self.check_watchers();
+            observe(self, record.as_ref());
+        };
+        // the output times from t on, including any at max_t itself, see the final state
+        self.fill_output_times(max_t);
+        Ok(reason)
+    }
+
+    pub fn run_until(
+        &mut self,
+        mut predicate: impl FnMut(&Population) -> bool,
+    ) -> Result<(f64, usize), SimError> {
+        // step until the predicate holds (or no further event can occur), returning the
+        // time reached and the number of steps (or leaps) taken
+
+        if self.history.checkpoints.is_empty() {
+            self.record_checkpoint();
+        }
+        let mut steps = 0;
+        while !predicate(self) && self.progress(f64::INFINITY)? {
+            self.record_checkpoint();
+            self.check_watchers();
+            steps += 1;
+        }
+        Ok((self.t, steps))
+    }
+}
+
+fn count_neighborhood(individual: &mut Individual, near: impl IntoIterator<Item = (f64, bool)>) {
+    // an individual's densities and infection pressure from the distances to those near
+    // it and whether each is infected
+    individual.birth_neighbor_density = 0.0;
+    individual.death_neighbor_density = 0.0;
+    individual.move_neighbor_density = 0.0;
+    individual.infection_pressure = 0.0;
+    for (d, infected) in near {
+        individual.add_neighbor(d, 1.0);
+        if infected {
+            individual.add_infected_neighbor(d, 1.0);
+        }
+    }
+}
+
+fn individual_rates(
+    individual: &mut Individual,
+    (birth_quality, death_quality): (f64, f64),
+    throttled: bool,
+) -> Result<f64, SimError> {
+    // an individual's event rates from its neighborhood and the habitat quality where it
+    // is, returning their total; rates that would corrupt event selection are rejected
+
+    individual.update_neighbor_weights();
+    individual.update_probabilities(birth_quality, death_quality);
+    if throttled {
+        individual.p_birth = 0.0;
+    }
+    let mut total = 0.0;
+    for (event, rate) in individual.event_rates() {
+        if !rate.is_finite() || rate < 0.0 {
+            return Err(SimError::InvalidRate {
+                uid: individual.uid,
+                event,
+                rate,
+            });
+        }
+        total += rate;
+    }
+    Ok(total)
+}
+
+fn for_each_individual<'a, T: Send>(
+    individuals: &mut [Individual<'a>],
+    update: impl Fn(usize, &mut Individual<'a>) -> T + Sync,
+) -> Vec<T> {
+    // Apply an update to every individual with its index, returning the results in
+    // index order. With the parallel feature, a large population is split into one
+    // contiguous chunk per core, each updated on its own scoped thread; the results do
+    // not depend on the split
+
+    #[cfg(feature = "parallel")]
+    if individuals.len() >= PARALLEL_MIN {
+        let threads = thread::available_parallelism().map_or(1, |x| x.get());
+        let chunk = individuals.len().div_ceil(threads);
+        let update = &update;
+        return thread::scope(|scope| {
+            let handles: Vec<_> = individuals
+                .chunks_mut(chunk)
+                .enumerate()
+                .map(|(k, part)| {
+                    scope.spawn(move || {
+                        let start = k * chunk;
+                        part.iter_mut()
+                            .enumerate()
+                            .map(|(i, x)| update(start + i, x))
+                            .collect::<Vec<T>>()
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|x| x.join().unwrap())
+                .collect()
+        });
+    }
+    individuals
+        .iter_mut()
+        .enumerate()
+        .map(|(idx, x)| update(idx, x))
+        .collect()
+}
@@ -0,0 +1,126 @@
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::Population;
+
+pub struct Profile {
+    pub steps: usize,
+    pub simulated_t: f64,
+    pub neighbor_updates: Duration,
+    pub sampling: Duration,
+    pub events: Duration,
+    pub checkpointing: Duration,
+    // wall-clock time still needed to go on from the end of the calibration to max_t
+    pub projected: Duration,
+}
+
+impl Profile {
+    pub fn total(&self) -> Duration {
+        self.neighbor_updates + self.sampling + self.events + self.checkpointing
+    }
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let total = self.total().as_secs_f64().max(f64::EPSILON);
+        let share = |d: Duration| 100.0 * d.as_secs_f64() / total;
+
+        writeln!(
+            f,
+            "{} steps over t = {:.3} in {:.3?}",
+            self.steps,
+            self.simulated_t,
+            self.total()
+        )?;
+        writeln!(
+            f,
+            "  neighbor updates {:>10.3?} ({:.1}%)",
+            self.neighbor_updates,
+            share(self.neighbor_updates)
+        )?;
+        writeln!(
+            f,
+            "  sampling         {:>10.3?} ({:.1}%)",
+            self.sampling,
+            share(self.sampling)
+        )?;
+        writeln!(
+            f,
+            "  events           {:>10.3?} ({:.1}%)",
+            self.events,
+            share(self.events)
+        )?;
+        writeln!(
+            f,
+            "  checkpointing    {:>10.3?} ({:.1}%)",
+            self.checkpointing,
+            share(self.checkpointing)
+        )?;
+        write!(f, "  projected        {:>10.3?}", self.projected)
+    }
+}
+
+impl<'a> Population<'a> {
+    pub fn profile(&mut self, calibration_t: f64, max_t: f64) -> Profile {
+        // run a short calibration simulation, timing each phase of a step, and project
+        // the wall-clock time needed to carry on to max_t at the calibration's pace
+
+        let start_t = self.t;
+        let mut steps = 0;
+        let mut neighbor_updates = Duration::ZERO;
+        let mut sampling = Duration::ZERO;
+        let mut events = Duration::ZERO;
+        let mut checkpointing = Duration::ZERO;
+
+        let end = start_t + calibration_t;
+        while self.t < end {
+            let start = Instant::now();
+            let updated = self.update_rates();
+            neighbor_updates += start.elapsed();
+            if updated.is_err() {
+                break;
+            }
+
+            let start = Instant::now();
+            let choice = self.next_event();
+            sampling += start.elapsed();
+
+            let Some((event, idx, delta_t)) = choice else {
+                break;
+            };
+            // an event past the end of the calibration is left undone, as in simulate
+            if self.t + delta_t >= end {
+                self.advance(end - self.t);
+                break;
+            }
+            self.advance(delta_t);
+            let start = Instant::now();
+            self.execute(event, idx);
+            events += start.elapsed();
+
+            let start = Instant::now();
+            self.record_checkpoint();
+            checkpointing += start.elapsed();
+
+            steps += 1;
+        }
+
+        let simulated_t = self.t - start_t;
+        let elapsed = neighbor_updates + sampling + events + checkpointing;
+        let projected = if simulated_t > 0.0 {
+            elapsed.mul_f64((max_t - self.t).max(0.0) / simulated_t)
+        } else {
+            elapsed
+        };
+
+        Profile {
+            steps,
+            simulated_t,
+            neighbor_updates,
+            sampling,
+            events,
+            checkpointing,
+            projected,
+        }
+    }
+}
@@ -0,0 +1,62 @@
+use std::error;
+use std::fmt;
+
+use crate::{EventRecord, Population, SimError, SimulationConfig, Species};
+
+#[derive(Debug)]
+pub struct Divergence {
+    pub step: usize,
+    pub first: Option<EventRecord>,
+    pub second: Option<EventRecord>,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "runs diverged at step {}: {:?} vs {:?}",
+            self.step, self.first, self.second
+        )
+    }
+}
+
+impl error::Error for Divergence {}
+
+pub fn verify_replay(species_list: &[&Species], seed: u64, max_t: f64) -> Result<usize, SimError> {
+    // run the same seeded scenario twice in lockstep and return the number of matching
+    // steps, or the first step at which the two event logs disagree
+
+    let mut config = SimulationConfig::new(species_list.to_vec(), max_t);
+    config.seed = Some(seed);
+    verify_config_replay(&config)
+}
+
+pub fn verify_config_replay(config: &SimulationConfig) -> Result<usize, SimError> {
+    // verify_replay for a full configuration; without a seed one is drawn for both runs
+
+    let config = SimulationConfig {
+        seed: Some(config.seed.unwrap_or_else(rand::random)),
+        ..config.clone()
+    };
+    let mut first = Population::from_config(&config)?;
+    let mut second = Population::from_config(&config)?;
+
+    let mut step = 0;
+    loop {
+        let a = first.step_within(config.max_t)?;
+        let b = second.step_within(config.max_t)?;
+        if a != b {
+            return Err(Divergence {
+                step,
+                first: a,
+                second: b,
+            }
+            .into());
+        }
+        if a.is_none() {
+            break;
+        }
+        step += 1;
+    }
+    Ok(step)
+}
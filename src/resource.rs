@@ -0,0 +1,61 @@
+use ndarray::Array2;
+
+#[derive(Clone, Debug)]
+pub struct Resource {
+    pub level: Array2<f64>,
+    pub capacity: f64,
+    pub growth: f64,
+    pub consumption: f64,
+}
+
+impl Resource {
+    pub fn new(resolution: usize, capacity: f64, growth: f64, consumption: f64) -> Self {
+        // A resolution x resolution resource grid starting at carrying capacity
+
+        Resource {
+            level: Array2::from_elem((resolution, resolution), capacity),
+            capacity,
+            growth,
+            consumption,
+        }
+    }
+
+    fn cell(&self, x_coord: f64, y_coord: f64) -> (usize, usize) {
+        let (rows, cols) = self.level.dim();
+        (
+            ((y_coord * rows as f64) as usize).min(rows - 1),
+            ((x_coord * cols as f64) as usize).min(cols - 1),
+        )
+    }
+
+    pub fn fraction_at(&self, x_coord: f64, y_coord: f64) -> f64 {
+        // Resource level relative to carrying capacity in the cell containing a point
+
+        if self.capacity > 0.0 {
+            self.level[self.cell(x_coord, y_coord)] / self.capacity
+        } else {
+            0.0
+        }
+    }
+
+    pub fn update(&mut self, positions: impl Iterator<Item = (f64, f64)>, delta_t: f64) {
+        // Advance the grid by delta_t with consumers held in place: logistic regrowth, then
+        // depletion proportional to the local number of consumers and the resource level
+        // (both steps solved exactly)
+
+        let mut consumers = Array2::<f64>::zeros(self.level.dim());
+        for (x, y) in positions {
+            consumers[self.cell(x, y)] += 1.0;
+        }
+
+        let regrowth = (-self.growth * delta_t).exp();
+        for (level, n) in self.level.iter_mut().zip(consumers.iter()) {
+            if *level > 0.0 {
+                *level = self.capacity / (1.0 + (self.capacity / *level - 1.0) * regrowth);
+            }
+            if self.capacity > 0.0 {
+                *level *= (-self.consumption * n * delta_t / self.capacity).exp();
+            }
+        }
+    }
+}
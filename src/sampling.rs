@@ -0,0 +1,294 @@
+use rand::Rng;
+
+use crate::individual::Sex;
+
+#[derive(Default)]
+pub struct CompensatedSum {
+    // Neumaier's variant of Kahan summation: the running sum plus the low-order bits
+    // lost from it so far
+    sum: f64,
+    compensation: f64,
+}
+
+impl CompensatedSum {
+    pub fn add(&mut self, value: f64) {
+        let sum = self.sum + value;
+        self.compensation += if self.sum.abs() >= value.abs() {
+            (self.sum - sum) + value
+        } else {
+            (value - sum) + self.sum
+        };
+        self.sum = sum;
+    }
+
+    pub fn value(&self) -> f64 {
+        self.sum + self.compensation
+    }
+}
+
+// Fenwick tree over the total event rate of each individual, so that changing one rate,
+// the sum of all of them, and finding the individual a cumulative rate falls in each take
+// O(log^2 n) or less. Node k (counting from 1) holds the sum of the rates over
+// (k - lowbit(k), k]; an update recomputes the nodes above a rate from their children
+// rather than shifting them by the difference, so no rounding builds up however many
+// updates they see, and a tree is the same bit for bit however its rates were reached.
+#[derive(Clone, Default, PartialEq)]
+pub struct RateTree {
+    rates: Vec<f64>,
+    nodes: Vec<f64>,
+}
+
+fn lowbit(k: usize) -> usize {
+    k & k.wrapping_neg()
+}
+
+impl RateTree {
+    pub fn from_rates(rates: impl IntoIterator<Item = f64>) -> Self {
+        let mut tree = RateTree::default();
+        rates.into_iter().for_each(|x| tree.push(x));
+        tree
+    }
+
+    pub fn len(&self) -> usize {
+        self.rates.len()
+    }
+
+    pub fn rate(&self, idx: usize) -> f64 {
+        self.rates[idx]
+    }
+
+    fn node(&self, k: usize) -> f64 {
+        // node k recomputed from its own rate and the nodes that make up the rest of its
+        // range
+        let mut sum = self.rates[k - 1];
+        let mut child = k - 1;
+        while child > k - lowbit(k) {
+            sum += self.nodes[child - 1];
+            child -= lowbit(child);
+        }
+        sum
+    }
+
+    pub fn push(&mut self, rate: f64) {
+        self.rates.push(rate);
+        self.nodes.push(0.0);
+        let k = self.rates.len();
+        self.nodes[k - 1] = self.node(k);
+    }
+
+    pub fn set(&mut self, idx: usize, rate: f64) {
+        self.rates[idx] = rate;
+        let mut k = idx + 1;
+        while k <= self.rates.len() {
+            self.nodes[k - 1] = self.node(k);
+            k += lowbit(k);
+        }
+    }
+
+    pub fn swap_remove(&mut self, idx: usize) {
+        // drop a rate, moving the last one into its place as Vec::swap_remove does; no
+        // node covers a range past its own index, so the last one goes with its rate
+        let last = self.rates.pop().expect("a rate to remove");
+        self.nodes.pop();
+        if idx < self.rates.len() {
+            self.set(idx, last);
+        }
+    }
+
+    pub fn total(&self) -> f64 {
+        let mut total = CompensatedSum::default();
+        let mut k = self.rates.len();
+        while k > 0 {
+            total.add(self.nodes[k - 1]);
+            k -= lowbit(k);
+        }
+        total.value()
+    }
+
+    pub fn find(&self, target: f64) -> Option<(usize, f64)> {
+        // The first rate at which the running total passes target, and how far into it
+        // target falls; rounding can leave target past the last rate, in which case the
+        // last positive rate is taken. None only when every rate is zero
+
+        let len = self.rates.len();
+        let mut idx = 0;
+        let mut remaining = target;
+        let mut step = if len == 0 { 0 } else { 1 << len.ilog2() };
+        while step > 0 {
+            if idx + step <= len && self.nodes[idx + step - 1] <= remaining {
+                idx += step;
+                remaining -= self.nodes[idx - 1];
+            }
+            step /= 2;
+        }
+        if idx < len {
+            return Some((idx, remaining));
+        }
+        let idx = self.rates.iter().rposition(|x| *x > 0.0)?;
+        Some((idx, self.rates[idx]))
+    }
+}
+
+pub fn sample_normal(rng: &mut impl Rng, mean: f64, sd: f64) -> f64 {
+    // Box-Muller draw from a normal distribution
+
+    let u1: f64 = 1.0 - rng.gen::<f64>();
+    let u2: f64 = rng.gen();
+    mean + sd * (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos()
+}
+
+pub fn sample_poisson(rng: &mut impl Rng, lambda: f64) -> usize {
+    // Knuth's multiplication method for the small means of clutch sizes, and Hormann's
+    // transformed rejection (PTRS) for the large ones of leaps, where the number of
+    // uniforms Knuth's method takes grows with the mean and exp(-mean) underflows
+
+    if lambda >= 10.0 {
+        return sample_poisson_ptrs(rng, lambda);
+    }
+    let limit = (-lambda).exp();
+    let mut count = 0;
+    let mut product: f64 = rng.gen();
+    while product > limit {
+        count += 1;
+        product *= rng.gen::<f64>();
+    }
+    count
+}
+
+fn sample_poisson_ptrs(rng: &mut impl Rng, lambda: f64) -> usize {
+    // W. Hormann, "The transformed rejection method for generating Poisson random
+    // variables" (1993), valid for means of at least 10
+
+    let log_lambda = lambda.ln();
+    let b = 0.931 + 2.53 * lambda.sqrt();
+    let a = -0.059 + 0.02483 * b;
+    let inv_alpha = 1.1239 + 1.1328 / (b - 3.4);
+    let v_r = 0.9277 - 3.6224 / (b - 2.0);
+    loop {
+        let u = rng.gen::<f64>() - 0.5;
+        let v: f64 = rng.gen();
+        let us = 0.5 - u.abs();
+        let k = ((2.0 * a / us + b) * u + lambda + 0.43).floor();
+        if us >= 0.07 && v <= v_r {
+            return k as usize;
+        }
+        if k < 0.0 || (us < 0.013 && v > us) {
+            continue;
+        }
+        let accept = -lambda + k * log_lambda - ln_factorial(k);
+        if (v * inv_alpha / (a / (us * us) + b)).ln() <= accept {
+            return k as usize;
+        }
+    }
+}
+
+fn ln_factorial(k: f64) -> f64 {
+    // summed directly for small k, else Stirling's series for ln Gamma(k + 1)
+
+    if k < 10.0 {
+        return (2..=k as usize).map(|i| (i as f64).ln()).sum();
+    }
+    let n = k + 1.0;
+    let n2 = n * n;
+    (n - 0.5) * n.ln() - n
+        + 0.5 * (2.0 * std::f64::consts::PI).ln()
+        + (1.0 / 12.0 - (1.0 / 360.0 - 1.0 / (1260.0 * n2)) / n2) / n
+}
+
+pub fn random_sex(rng: &mut impl Rng) -> Sex {
+    if rng.gen_bool(0.5) {
+        Sex::Female
+    } else {
+        Sex::Male
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha12Rng;
+
+    #[test]
+    fn compensated_sum_of_small_rates_is_exact_to_an_ulp() {
+        // A million rates below 4e-6 after one rate of 1e3, as when a few large
+        // rates precede many small ones in choose_event. Each rate is a multiple of
+        // 2^-70, so the exact total can be kept as an integer for reference.
+        let mut rng = ChaCha12Rng::seed_from_u64(7);
+        let scale = 2f64.powi(-70);
+        let mut units: Vec<u128> = vec![1000u128 << 70];
+        units.extend((0..1_000_000).map(|_| rng.gen_range(0..1u128 << 52)));
+
+        let mut compensated = CompensatedSum::default();
+        let mut naive = 0.0;
+        for unit in &units {
+            let rate = *unit as f64 * scale;
+            assert_eq!((rate / scale) as u128, *unit);
+            compensated.add(rate);
+            naive += rate;
+        }
+        let exact = units.iter().sum::<u128>() as f64 * scale;
+
+        let ulp = exact * f64::EPSILON;
+        assert!((compensated.value() - exact).abs() <= ulp);
+        // plain summation drifts by many ulps over the same rates
+        assert!((naive - exact).abs() > 10.0 * ulp);
+    }
+
+    #[test]
+    fn rate_tree_matches_a_rebuild_and_finds_cumulative_rates() {
+        // after many updates, pushes and removals the tree equals one built afresh from
+        // its rates, and find agrees with a linear walk over them
+        let mut rng = ChaCha12Rng::seed_from_u64(3);
+        let mut rates: Vec<f64> = (0..37).map(|_| rng.gen::<f64>()).collect();
+        let mut tree = RateTree::from_rates(rates.iter().copied());
+        for _ in 0..10_000 {
+            match rng.gen_range(0..4) {
+                0 => {
+                    let rate = rng.gen::<f64>() * 1e3;
+                    rates.push(rate);
+                    tree.push(rate);
+                }
+                1 if rates.len() > 1 => {
+                    let idx = rng.gen_range(0..rates.len());
+                    rates.swap_remove(idx);
+                    tree.swap_remove(idx);
+                }
+                _ => {
+                    let idx = rng.gen_range(0..rates.len());
+                    rates[idx] = if rng.gen_bool(0.2) { 0.0 } else { rng.gen() };
+                    tree.set(idx, rates[idx]);
+                }
+            }
+        }
+        let rebuilt = RateTree::from_rates(rates.iter().copied());
+        assert_eq!(tree.nodes, rebuilt.nodes);
+
+        let total = tree.total();
+        for _ in 0..1000 {
+            let target = rng.gen::<f64>() * total;
+            let (idx, remaining) = tree.find(target).unwrap();
+            let before: f64 = rates[..idx].iter().sum();
+            assert!(rates[idx] > 0.0);
+            assert!((before + remaining - target).abs() <= 1e-9 * total);
+            assert!(remaining < rates[idx] + 1e-9 * total);
+        }
+    }
+
+    #[test]
+    fn poisson_draws_match_the_mean_and_variance_of_large_means() {
+        let mut rng = ChaCha12Rng::seed_from_u64(11);
+        for lambda in [10.0, 250.0, 1e6] {
+            let draws = 200_000;
+            let samples: Vec<f64> = (0..draws)
+                .map(|_| sample_poisson(&mut rng, lambda) as f64)
+                .collect();
+            let mean = samples.iter().sum::<f64>() / draws as f64;
+            let variance =
+                samples.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (draws - 1) as f64;
+            // within five standard errors of the mean and roughly of the variance
+            assert!((mean - lambda).abs() < 5.0 * (lambda / draws as f64).sqrt());
+            assert!((variance / lambda - 1.0).abs() < 0.02);
+        }
+    }
+}
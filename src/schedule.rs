@@ -0,0 +1,115 @@
+use rand::seq::SliceRandom;
+
+use crate::history::HarvestRecord;
+use crate::population::PLACEMENT_ATTEMPTS;
+use crate::sampling::sample_normal;
+use crate::streams::Stream;
+use crate::{Population, Species};
+
+#[derive(Clone)]
+pub struct Harvest {
+    pub t: f64,
+    pub species: u8,
+    pub fraction: f64,
+    // optional [x_min, y_min, x_max, y_max] restricting the cull
+    pub region: Option<[f64; 4]>,
+}
+
+#[derive(Clone)]
+pub struct Introduction<'a> {
+    pub t: f64,
+    pub species: &'a Species,
+    pub founders: usize,
+    // release point and the normal spread of founders around it
+    pub location: (f64, f64),
+    pub spread: f64,
+}
+
+impl<'a> Population<'a> {
+    pub fn schedule_harvest(&mut self, harvest: Harvest) {
+        // Queue a harvest, keeping the queue ordered by time
+
+        let position = self.harvests.partition_point(|x| x.t <= harvest.t);
+        self.harvests.insert(position, harvest);
+    }
+
+    pub(crate) fn next_harvest(&self) -> Option<(usize, f64)> {
+        self.harvests.first().map(|x| (0, x.t))
+    }
+
+    pub(crate) fn execute_harvest(&mut self, idx: usize) {
+        // remove the given fraction of the targeted individuals, chosen at random
+
+        let harvest = self.harvests.remove(idx);
+        let targets: Vec<usize> = self
+            .individuals
+            .iter()
+            .enumerate()
+            .filter(|(_, x)| x.species.id == harvest.species)
+            .filter(|(_, x)| {
+                harvest.region.is_none_or(|[x_min, y_min, x_max, y_max]| {
+                    x_min <= x.x_coord
+                        && x.x_coord < x_max
+                        && y_min <= x.y_coord
+                        && x.y_coord < y_max
+                })
+            })
+            .map(|(idx, _)| idx)
+            .collect();
+
+        let count = (harvest.fraction.clamp(0.0, 1.0) * targets.len() as f64).round() as usize;
+        let mut culled: Vec<usize> = targets
+            .choose_multiple(self.streams.get(Stream::Schedule), count)
+            .copied()
+            .collect();
+        // remove from the back so earlier indices stay valid
+        culled.sort_unstable_by(|a, b| b.cmp(a));
+        for idx in &culled {
+            self.execute_death(*idx);
+        }
+
+        self.history.harvests.push(HarvestRecord {
+            t: self.t,
+            species: harvest.species,
+            removed: culled.len(),
+        });
+    }
+
+    pub fn schedule_introduction(&mut self, introduction: Introduction<'a>) {
+        // Queue the release of a species' founders, registering the species so that it
+        // also takes part in immigration
+
+        if !self.species.iter().any(|x| x.id == introduction.species.id) {
+            self.species.push(introduction.species);
+            self.rebuild_grid();
+        }
+        let position = self
+            .introductions
+            .partition_point(|x| x.t <= introduction.t);
+        self.introductions.insert(position, introduction);
+    }
+
+    pub(crate) fn next_introduction(&self) -> Option<(usize, f64)> {
+        self.introductions.first().map(|x| (0, x.t))
+    }
+
+    pub(crate) fn execute_introduction(&mut self, idx: usize) {
+        // release the founders around the release point, skipping any that cannot be
+        // placed outside the obstacles
+
+        let introduction = self.introductions.remove(idx);
+        let (x_release, y_release) = introduction.location;
+        for _ in 0..introduction.founders {
+            for _ in 0..PLACEMENT_ATTEMPTS {
+                let rng = self.streams.get(Stream::Schedule);
+                let x_coord = sample_normal(rng, x_release, introduction.spread).rem_euclid(1.0);
+                let y_coord = sample_normal(rng, y_release, introduction.spread).rem_euclid(1.0);
+                if !self.blocked(x_coord, y_coord) {
+                    let founder = self.new_arrival(introduction.species, x_coord, y_coord);
+                    self.add_individual(founder);
+                    break;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,365 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use ndarray::Array2;
+
+use crate::archive::{self, invalid, Decoder, Encoder};
+use crate::genealogy::{Genealogy, Lineage};
+use crate::history::History;
+use crate::individual::{Health, Sex, Stage};
+use crate::metadata::species_hash;
+use crate::resource::Resource;
+use crate::schedule::{Harvest, Introduction};
+use crate::streams::Streams;
+use crate::{Individual, Population, Species};
+
+// On-disk form of a snapshot, in the same little-endian layout as history archives:
+// magic bytes, the state format version, a hash of the species parameters of the
+// population it was taken from, and the fields in declaration order with the history
+// encoded as in an archive of the version recorded alongside it. Species are stored by
+// id and looked up in the population being resumed, which must have the same parameters.
+const MAGIC: &[u8; 4] = b"PDSS";
+const VERSION: u16 = 1;
+
+// Everything that changes while a population runs, including the rng streams, so that a
+// resumed run continues exactly as the original would have. Species, habitats, obstacles,
+// caps, and watchers are configuration and stay with the population being resumed.
+pub(crate) struct Snapshot<'a> {
+    individuals: Vec<Individual<'a>>,
+    size: usize,
+    t: f64,
+    seed: u64,
+    streams: Streams,
+    resource: Option<Resource>,
+    harvests: Vec<Harvest>,
+    introductions: Vec<Introduction<'a>>,
+    history: History,
+    genealogy: Genealogy,
+}
+
+impl<'a> Population<'a> {
+    pub(crate) fn snapshot(&self) -> Snapshot<'a> {
+        Snapshot {
+            individuals: self.individuals.clone(),
+            size: self.size,
+            t: self.t,
+            seed: self.seed,
+            streams: self.streams.clone(),
+            resource: self.resource.clone(),
+            harvests: self.harvests.clone(),
+            introductions: self.introductions.clone(),
+            history: self.history.clone(),
+            genealogy: self.genealogy.clone(),
+        }
+    }
+
+    pub(crate) fn resume(&mut self, snapshot: Snapshot<'a>) {
+        // restore a snapshot taken from this population (or one configured identically)
+
+        self.individuals = snapshot.individuals;
+        self.size = snapshot.size;
+        self.t = snapshot.t;
+        self.seed = snapshot.seed;
+        self.streams = snapshot.streams;
+        self.resource = snapshot.resource;
+        self.harvests = snapshot.harvests;
+        self.introductions = snapshot.introductions;
+        self.history = snapshot.history;
+        self.genealogy = snapshot.genealogy;
+        self.rebuild_grid();
+        self.stale.clear();
+        self.all_stale = true;
+    }
+}
+
+impl Encoder {
+    fn individual(&mut self, individual: &Individual) {
+        self.u64(individual.id);
+        self.u64(individual.uid);
+        self.u8(individual.species.id);
+        self.f64(individual.x_coord);
+        self.f64(individual.y_coord);
+        self.f64(individual.anchor.0);
+        self.f64(individual.anchor.1);
+        self.u8(individual.stage as u8);
+        self.u8(individual.sex as u8);
+        self.f64(individual.trait_value);
+        self.f64(individual.birth_time);
+        self.u64(individual.genome.len());
+        self.bytes.extend(&individual.genome);
+        self.u8(individual.health as u8);
+        [
+            individual.p_birth,
+            individual.p_death,
+            individual.p_move,
+            individual.p_mature,
+            individual.p_infect,
+            individual.p_recover,
+            individual.p_disease_death,
+            individual.birth_neighbor_density,
+            individual.death_neighbor_density,
+            individual.move_neighbor_density,
+            individual.birth_neighbor_weight,
+            individual.death_neighbor_weight,
+            individual.move_neighbor_weight,
+            individual.infection_pressure,
+        ]
+        .into_iter()
+        .for_each(|x| self.f64(x));
+    }
+
+    fn region(&mut self, region: &Option<[f64; 4]>) {
+        self.flag(region);
+        region
+            .unwrap_or_default()
+            .into_iter()
+            .for_each(|x| self.f64(x));
+    }
+}
+
+impl Decoder<'_> {
+    fn tag<T: Copy>(&mut self, values: &[T], what: &str) -> io::Result<T> {
+        let tag = self.u8()?;
+        values
+            .get(tag as usize)
+            .copied()
+            .ok_or_else(|| invalid(format!("invalid {what} {tag} in state file")))
+    }
+
+    fn individual<'a>(&mut self, species: &[&'a Species]) -> io::Result<Individual<'a>> {
+        let (id, uid) = (self.u64()?, self.u64()?);
+        let species = lookup(species, self.u8()?)?;
+        let mut individual = Individual::new(id, species, self.f64()?, self.f64()?);
+        individual.uid = uid;
+        individual.anchor = (self.f64()?, self.f64()?);
+        individual.stage = self.tag(&[Stage::Juvenile, Stage::Adult], "stage")?;
+        individual.sex = self.tag(&[Sex::Female, Sex::Male], "sex")?;
+        individual.trait_value = self.f64()?;
+        individual.birth_time = self.f64()?;
+        let len = self.u64()?;
+        individual.genome = self.take(len)?.to_vec();
+        individual.health = self.tag(
+            &[Health::Susceptible, Health::Infected, Health::Recovered],
+            "health",
+        )?;
+        for value in [
+            &mut individual.p_birth,
+            &mut individual.p_death,
+            &mut individual.p_move,
+            &mut individual.p_mature,
+            &mut individual.p_infect,
+            &mut individual.p_recover,
+            &mut individual.p_disease_death,
+            &mut individual.birth_neighbor_density,
+            &mut individual.death_neighbor_density,
+            &mut individual.move_neighbor_density,
+            &mut individual.birth_neighbor_weight,
+            &mut individual.death_neighbor_weight,
+            &mut individual.move_neighbor_weight,
+            &mut individual.infection_pressure,
+        ] {
+            *value = self.f64()?;
+        }
+        Ok(individual)
+    }
+
+    fn region(&mut self) -> io::Result<Option<[f64; 4]>> {
+        let present = self.flag()?;
+        let region = [self.f64()?, self.f64()?, self.f64()?, self.f64()?];
+        Ok(present.then_some(region))
+    }
+}
+
+fn lookup<'a>(species: &[&'a Species], id: u8) -> io::Result<&'a Species> {
+    species
+        .iter()
+        .find(|x| x.id == id)
+        .copied()
+        .ok_or_else(|| invalid(format!("state file refers to unknown species {id}")))
+}
+
+impl<'a> Snapshot<'a> {
+    fn encode(&self, encoder: &mut Encoder) {
+        encoder.u64(self.size);
+        self.individuals.iter().for_each(|x| encoder.individual(x));
+        encoder.f64(self.t);
+        encoder.bytes.extend(self.seed.to_le_bytes());
+        for (seed, position) in self.streams.state() {
+            encoder.bytes.extend(seed);
+            encoder.bytes.extend(position.to_le_bytes());
+        }
+
+        encoder.flag(&self.resource);
+        if let Some(resource) = &self.resource {
+            encoder.u64(resource.level.nrows());
+            encoder.u64(resource.level.ncols());
+            resource.level.iter().for_each(|x| encoder.f64(*x));
+            encoder.f64(resource.capacity);
+            encoder.f64(resource.growth);
+            encoder.f64(resource.consumption);
+        }
+        encoder.u64(self.harvests.len());
+        for harvest in &self.harvests {
+            encoder.f64(harvest.t);
+            encoder.u8(harvest.species);
+            encoder.f64(harvest.fraction);
+            encoder.region(&harvest.region);
+        }
+        encoder.u64(self.introductions.len());
+        for introduction in &self.introductions {
+            encoder.f64(introduction.t);
+            encoder.u8(introduction.species.id);
+            encoder.u64(introduction.founders);
+            encoder.f64(introduction.location.0);
+            encoder.f64(introduction.location.1);
+            encoder.f64(introduction.spread);
+        }
+
+        encoder.bytes.extend(archive::VERSION.to_le_bytes());
+        encoder.history(&self.history);
+        encoder.u64(self.genealogy.lineages.len());
+        for lineage in &self.genealogy.lineages {
+            encoder.u64(lineage.uid);
+            encoder.flag(&lineage.parent);
+            encoder.u64(lineage.parent.unwrap_or(0));
+            encoder.u8(lineage.species);
+            encoder.f64(lineage.birth_time);
+            encoder.flag(&lineage.death_time);
+            encoder.f64(lineage.death_time.unwrap_or(0.0));
+        }
+    }
+
+    fn decode(decoder: &mut Decoder, species: &[&'a Species]) -> io::Result<Self> {
+        let size = decoder.u64()?;
+        let individuals = decoder.many(size, |d| d.individual(species))?;
+        let t = decoder.f64()?;
+        let seed = u64::from_le_bytes(decoder.take(8)?.try_into().unwrap());
+        let mut state = [([0; 32], 0); 6];
+        for (seed, position) in state.iter_mut() {
+            seed.copy_from_slice(decoder.take(32)?);
+            *position = u128::from_le_bytes(decoder.take(16)?.try_into().unwrap());
+        }
+        let streams = Streams::from_state(state);
+
+        let resource = if decoder.flag()? {
+            let (rows, cols) = (decoder.u64()?, decoder.u64()?);
+            let values = decoder.many(rows.saturating_mul(cols), Decoder::f64)?;
+            Some(Resource {
+                level: Array2::from_shape_vec((rows, cols), values)
+                    .map_err(|e| invalid(e.to_string()))?,
+                capacity: decoder.f64()?,
+                growth: decoder.f64()?,
+                consumption: decoder.f64()?,
+            })
+        } else {
+            None
+        };
+        let count = decoder.u64()?;
+        let harvests = decoder.many(count, |d| {
+            Ok(Harvest {
+                t: d.f64()?,
+                species: d.u8()?,
+                fraction: d.f64()?,
+                region: d.region()?,
+            })
+        })?;
+        let count = decoder.u64()?;
+        let introductions = decoder.many(count, |d| {
+            Ok(Introduction {
+                t: d.f64()?,
+                species: lookup(species, d.u8()?)?,
+                founders: d.u64()?,
+                location: (d.f64()?, d.f64()?),
+                spread: d.f64()?,
+            })
+        })?;
+
+        let version = u16::from_le_bytes(decoder.take(2)?.try_into().unwrap());
+        if version != archive::VERSION {
+            return Err(invalid(format!(
+                "unsupported history version {version} in state file"
+            )));
+        }
+        let history = decoder.history()?;
+        let count = decoder.u64()?;
+        let lineages = decoder.many(count, |d| {
+            let uid = d.u64()?;
+            let parent = (d.flag()?, d.u64()?);
+            let species = d.u8()?;
+            let birth_time = d.f64()?;
+            let death_time = (d.flag()?, d.f64()?);
+            Ok(Lineage {
+                uid,
+                parent: parent.0.then_some(parent.1),
+                species,
+                birth_time,
+                death_time: death_time.0.then_some(death_time.1),
+            })
+        })?;
+
+        Ok(Snapshot {
+            individuals,
+            size,
+            t,
+            seed,
+            streams,
+            resource,
+            harvests,
+            introductions,
+            history,
+            genealogy: Genealogy { lineages },
+        })
+    }
+}
+
+impl<'a> Population<'a> {
+    pub fn state_bytes(&self) -> Vec<u8> {
+        let mut encoder = Encoder { bytes: vec![] };
+        encoder.bytes.extend(MAGIC);
+        encoder.bytes.extend(VERSION.to_le_bytes());
+        encoder
+            .bytes
+            .extend(species_hash(&self.species).to_le_bytes());
+        self.snapshot().encode(&mut encoder);
+        encoder.bytes
+    }
+
+    pub fn resume_from_bytes(&mut self, bytes: &[u8]) -> io::Result<()> {
+        // Restore a state written by state_bytes from a population with the same species
+        // parameters; the configuration (caps, stepping, habitats and the like) is this
+        // population's own
+
+        let mut decoder = Decoder { bytes };
+        if decoder.take(4).ok() != Some(MAGIC.as_slice()) {
+            return Err(invalid("not a state file"));
+        }
+        let version = u16::from_le_bytes(decoder.take(2)?.try_into().unwrap());
+        if version != VERSION {
+            return Err(invalid(format!(
+                "unsupported state file version {version} (expected {VERSION})"
+            )));
+        }
+        let hash = u64::from_le_bytes(decoder.take(8)?.try_into().unwrap());
+        if hash != species_hash(&self.species) {
+            return Err(invalid(
+                "state file was saved with different species parameters",
+            ));
+        }
+        let snapshot = Snapshot::decode(&mut decoder, &self.species)?;
+        if !decoder.bytes.is_empty() {
+            return Err(invalid("trailing bytes after state file"));
+        }
+        self.resume(snapshot);
+        Ok(())
+    }
+
+    pub fn save_state(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(&path, self.state_bytes())?;
+        self.metadata().write_sidecar(path)
+    }
+
+    pub fn resume_from(&mut self, path: impl AsRef<Path>) -> io::Result<()> {
+        self.resume_from_bytes(&fs::read(path)?)
+    }
+}
@@ -0,0 +1,245 @@
+use std::error;
+use std::fmt;
+
+use crate::heatmap::hue_to_rgb;
+use crate::pattern::{OutOfDomain, Pattern};
+
+#[derive(Clone, Debug, Default)]
+#[allow(non_snake_case)]
+pub struct Species {
+    pub id: u8,
+    pub B0: f64,
+    pub B1: f64,
+    pub C1: f64,
+    pub D0: f64,
+    pub D1: f64,
+    pub M1: f64,
+    pub A0: f64,
+    pub A1: f64,
+    pub Rexcl: f64,
+    pub I0: f64,
+    pub Iedge: bool,
+    pub Mdsd: f64,
+    pub Mbrmax: f64,
+    pub Mbsd: f64,
+    pub Mintegral: f64,
+    pub Mrmax: f64,
+    pub Msd: f64,
+    pub Wbrmax: f64,
+    pub Wbsd: f64,
+    pub Wdrmax: f64,
+    pub Wdsd: f64,
+    pub G0: f64,
+    pub JB0: f64,
+    pub JB1: f64,
+    pub JD0: f64,
+    pub JD1: f64,
+    pub Rmate: f64,
+    pub T0: f64,
+    pub Tmut: f64,
+    pub TB: f64,
+    pub TD: f64,
+    pub Lmax: f64,
+    pub Nloci: usize,
+    pub Nalleles: u8,
+    pub Nmut: f64,
+    pub Eprev: f64,
+    pub Ebeta: f64,
+    pub Ermax: f64,
+    pub Esd: f64,
+    pub Egamma: f64,
+    pub Ealpha: f64,
+    pub Eimmune: bool,
+    pub Clutch: f64,
+    pub Hrange: f64,
+    pub Xcon: f64,
+    pub Xhet: f64,
+    pub Init: Pattern,
+    // optional label and RGB display colour, used by exports in place of the id
+    pub name: Option<String>,
+    pub color: Option<[u8; 3]>,
+}
+
+#[derive(Debug)]
+pub enum SpeciesError {
+    NegativeRate(&'static str, f64),
+    NegativeRadius(&'static str, f64),
+    NegativeSd(&'static str, f64),
+    NotAProbability(&'static str, f64),
+    NotFinite(&'static str, f64),
+    // a fixed starting position of the initial pattern
+    Init(OutOfDomain),
+}
+
+impl fmt::Display for SpeciesError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SpeciesError::NegativeRate(name, value) => {
+                write!(f, "rate {name} = {value} must be non-negative")
+            }
+            SpeciesError::NegativeRadius(name, value) => {
+                write!(f, "radius {name} = {value} must be non-negative")
+            }
+            SpeciesError::NegativeSd(name, value) => {
+                write!(
+                    f,
+                    "standard deviation {name} = {value} must be non-negative"
+                )
+            }
+            SpeciesError::NotAProbability(name, value) => {
+                write!(f, "probability {name} = {value} must lie in [0, 1]")
+            }
+            SpeciesError::NotFinite(name, value) => {
+                write!(f, "parameter {name} = {value} must be finite")
+            }
+            SpeciesError::Init(err) => write!(f, "initial pattern: {err}"),
+        }
+    }
+}
+
+impl error::Error for SpeciesError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            SpeciesError::Init(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+pub fn palette(id: usize, count: usize) -> [u8; 3] {
+    // Default display colour: evenly spaced hues over the species ids in use, so any
+    // number of species gets distinct colours
+
+    hue_to_rgb(id as f64 / count.max(1) as f64).map(|x| (x * 255.0).round() as u8)
+}
+
+impl Species {
+    pub fn label(&self) -> String {
+        self.name
+            .clone()
+            .unwrap_or_else(|| format!("species {}", self.id))
+    }
+
+    pub fn display_color(&self, count: usize) -> [u8; 3] {
+        // the species' own colour, else its palette colour among count species ids
+        self.color
+            .unwrap_or_else(|| palette(self.id as usize, count))
+    }
+
+    pub fn interaction_radius(&self) -> f64 {
+        // farthest any other individual can affect one of this species or be affected
+        // by it: its kernels, its mate search, and its exclusion zone
+        [
+            self.Wbrmax,
+            self.Wdrmax,
+            self.Mrmax,
+            self.Ermax,
+            self.Rmate,
+            self.Rexcl,
+        ]
+        .into_iter()
+        .fold(0.0, f64::max)
+    }
+
+    pub fn new(species: Species) -> Result<Species, SpeciesError> {
+        // Check a species' parameters before it is handed to a population; kernel norms
+        // are always derived from the radius and sd when weights are computed
+
+        species.validate()?;
+        Ok(species)
+    }
+
+    pub fn validate(&self) -> Result<(), SpeciesError> {
+        let species = self;
+
+        let rates = [
+            ("B0", species.B0),
+            ("D0", species.D0),
+            ("JB0", species.JB0),
+            ("JD0", species.JD0),
+            ("C1", species.C1),
+            ("I0", species.I0),
+            ("G0", species.G0),
+            ("Mintegral", species.Mintegral),
+            ("Ebeta", species.Ebeta),
+            ("Egamma", species.Egamma),
+            ("Ealpha", species.Ealpha),
+            ("Clutch", species.Clutch),
+            ("Lmax", species.Lmax),
+        ];
+        let radii = [
+            ("Rexcl", species.Rexcl),
+            ("Mbrmax", species.Mbrmax),
+            ("Mrmax", species.Mrmax),
+            ("Wbrmax", species.Wbrmax),
+            ("Wdrmax", species.Wdrmax),
+            ("Ermax", species.Ermax),
+            ("Rmate", species.Rmate),
+            ("Hrange", species.Hrange),
+        ];
+        let sds = [
+            ("Mdsd", species.Mdsd),
+            ("Mbsd", species.Mbsd),
+            ("Msd", species.Msd),
+            ("Wbsd", species.Wbsd),
+            ("Wdsd", species.Wdsd),
+            ("Esd", species.Esd),
+            ("Tmut", species.Tmut),
+        ];
+
+        // parameters that may take either sign
+        let signed = [
+            ("B1", species.B1),
+            ("D1", species.D1),
+            ("M1", species.M1),
+            ("A0", species.A0),
+            ("JB1", species.JB1),
+            ("JD1", species.JD1),
+            ("T0", species.T0),
+            ("TB", species.TB),
+            ("TD", species.TD),
+            ("Xcon", species.Xcon),
+            ("Xhet", species.Xhet),
+        ];
+
+        // NaN is rejected along with negative values, and infinities outright since they
+        // turn positions and rates into NaN mid-run
+        for (name, value) in rates.iter().chain(&radii).chain(&sds) {
+            if value.is_infinite() {
+                return Err(SpeciesError::NotFinite(name, *value));
+            }
+        }
+        for (name, value) in signed {
+            if !value.is_finite() {
+                return Err(SpeciesError::NotFinite(name, value));
+            }
+        }
+        for (name, value) in rates {
+            if value.is_nan() || value < 0.0 {
+                return Err(SpeciesError::NegativeRate(name, value));
+            }
+        }
+        for (name, value) in radii {
+            if value.is_nan() || value < 0.0 {
+                return Err(SpeciesError::NegativeRadius(name, value));
+            }
+        }
+        for (name, value) in sds {
+            if value.is_nan() || value < 0.0 {
+                return Err(SpeciesError::NegativeSd(name, value));
+            }
+        }
+        // A1 is the largest fraction of births the Allee effect removes
+        for (name, value) in [
+            ("Eprev", species.Eprev),
+            ("Nmut", species.Nmut),
+            ("A1", species.A1),
+        ] {
+            if !(0.0..=1.0).contains(&value) {
+                return Err(SpeciesError::NotAProbability(name, value));
+            }
+        }
+        species.Init.validate().map_err(SpeciesError::Init)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,47 @@
+use crate::{EventRecord, Population, SimError};
+
+pub struct Steps<'p, 'a> {
+    population: &'p mut Population<'a>,
+    done: bool,
+}
+
+impl<'a> Population<'a> {
+    pub fn steps(&mut self) -> Steps<'_, 'a> {
+        // drive the simulation one event at a time, recording history as simulate does;
+        // the population itself can be inspected again once the iterator is dropped
+
+        if self.history.checkpoints.is_empty() {
+            self.record_checkpoint();
+        }
+        Steps {
+            population: self,
+            done: false,
+        }
+    }
+}
+
+impl Iterator for Steps<'_, '_> {
+    type Item = Result<EventRecord, SimError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match self.population.step() {
+            Ok(Some(record)) => {
+                self.population.record_checkpoint();
+                self.population.check_watchers();
+                Some(Ok(record))
+            }
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(err) => {
+                // an invalid state cannot be stepped past
+                self.done = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
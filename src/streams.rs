@@ -0,0 +1,54 @@
+use rand::SeedableRng;
+use rand_chacha::ChaCha12Rng;
+
+// Independent random number streams, one per kind of draw, so that changing how often
+// one process draws (e.g. a different movement rate) leaves the others' sequences
+// untouched; runs compared under the same seed then share common random numbers
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Stream {
+    Initial,
+    Events,
+    Birth,
+    Movement,
+    Immigration,
+    Schedule,
+}
+
+// the generator behind rand's StdRng, used directly so that its position can be saved
+// and restored; it draws the same sequences StdRng would from the same seed
+#[derive(Clone)]
+pub struct Streams {
+    rngs: [ChaCha12Rng; 6],
+}
+
+impl Streams {
+    pub fn new(seed: u64) -> Self {
+        // each stream is seeded from the run seed mixed with the stream's index
+        let stream =
+            |k: u64| ChaCha12Rng::seed_from_u64(seed ^ (k + 1).wrapping_mul(0x9e37_79b9_7f4a_7c15));
+        Streams {
+            rngs: [0, 1, 2, 3, 4, 5].map(stream),
+        }
+    }
+
+    pub fn get(&mut self, stream: Stream) -> &mut ChaCha12Rng {
+        &mut self.rngs[stream as usize]
+    }
+
+    pub fn state(&self) -> [([u8; 32], u128); 6] {
+        // each stream's seed and offset in 32-bit words, enough to recreate it exactly
+        self.rngs
+            .each_ref()
+            .map(|x| (x.get_seed(), x.get_word_pos()))
+    }
+
+    pub fn from_state(state: [([u8; 32], u128); 6]) -> Self {
+        Streams {
+            rngs: state.map(|(seed, position)| {
+                let mut rng = ChaCha12Rng::from_seed(seed);
+                rng.set_word_pos(position);
+                rng
+            }),
+        }
+    }
+}
@@ -0,0 +1,88 @@
+use crate::analysis::extinction_times;
+use crate::config::SimulationConfig;
+use crate::{Population, SimError, Species, StopReason};
+
+type Setter = Box<dyn Fn(&mut Species, f64)>;
+
+// one swept parameter: a label, the index of the species in the base config (None for
+// every species), the values to try, and how to apply a value, e.g. |s, v| s.B1 = v
+pub struct SweepAxis {
+    pub name: String,
+    pub species: Option<usize>,
+    pub values: Vec<f64>,
+    pub set: Setter,
+}
+
+pub struct SweepRow {
+    // the value taken on each axis, in axis order
+    pub values: Vec<f64>,
+    pub reason: StopReason,
+    pub t: f64,
+    // final abundance and extinction time, indexed by species id
+    pub abundances: Vec<usize>,
+    pub extinctions: Vec<Option<f64>>,
+}
+
+pub fn sweep_with<E>(
+    base: &SimulationConfig,
+    axes: &[SweepAxis],
+    mut run: impl FnMut(usize, &[f64], &SimulationConfig) -> Result<(), E>,
+) -> Result<(), E> {
+    // Hand run the index, axis values and configuration of every combination of axis
+    // values in turn, the last axis varying fastest
+
+    let combinations: usize = axes.iter().map(|x| x.values.len()).product();
+    for combination in 0..combinations {
+        // decode the combination as a mixed-radix number
+        let mut values = vec![0.0; axes.len()];
+        let mut rest = combination;
+        for (k, axis) in axes.iter().enumerate().rev() {
+            values[k] = axis.values[rest % axis.values.len()];
+            rest /= axis.values.len();
+        }
+
+        let mut species: Vec<Species> = base.species.iter().map(|x| (*x).clone()).collect();
+        for (axis, value) in axes.iter().zip(&values) {
+            match axis.species {
+                Some(idx) => (axis.set)(&mut species[idx], *value),
+                None => species.iter_mut().for_each(|x| (axis.set)(x, *value)),
+            }
+        }
+        let mut config = base.clone();
+        config.species = species.iter().collect();
+        run(combination, &values, &config)?;
+    }
+    Ok(())
+}
+
+pub fn sweep(base: &SimulationConfig, axes: &[SweepAxis]) -> Result<Vec<SweepRow>, SimError> {
+    // Run the base configuration once for every combination of axis values, with the
+    // same seed throughout so that runs differ only in their parameters
+
+    let mut base = base.clone();
+    base.seed = Some(base.seed.unwrap_or_else(rand::random));
+    let mut rows = vec![];
+    sweep_with(&base, axes, |_, values, config| -> Result<(), SimError> {
+        let mut population = Population::from_config(config)?;
+        let reason = population.simulate(config.max_t)?;
+        let ids = population
+            .species
+            .iter()
+            .map(|x| x.id as usize + 1)
+            .max()
+            .unwrap_or(0);
+        let mut abundances = vec![0; ids];
+        for individual in &population.individuals {
+            abundances[individual.species.id as usize] += 1;
+        }
+        rows.push(SweepRow {
+            values: values.to_vec(),
+            reason,
+            t: population.t,
+            abundances,
+            extinctions: extinction_times(&population),
+        });
+        Ok(())
+    })?;
+    Ok(rows)
+}
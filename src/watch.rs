@@ -0,0 +1,117 @@
+use std::fmt;
+
+use crate::Population;
+
+#[derive(Clone, Debug)]
+pub enum Condition {
+    CountBelow {
+        species: u8,
+        count: usize,
+    },
+    CountAbove {
+        species: u8,
+        count: usize,
+    },
+    DensityAbove {
+        // region as [x_min, y_min, x_max, y_max] within the unit square
+        region: [f64; 4],
+        species: Option<u8>,
+        density: f64,
+    },
+}
+
+impl Condition {
+    pub fn holds(&self, population: &Population) -> bool {
+        let count = |species: u8| {
+            population
+                .individuals
+                .iter()
+                .filter(|x| x.species.id == species)
+                .count()
+        };
+
+        match self {
+            Condition::CountBelow { species, count: n } => count(*species) < *n,
+            Condition::CountAbove { species, count: n } => count(*species) > *n,
+            Condition::DensityAbove {
+                region,
+                species,
+                density,
+            } => {
+                let [x_min, y_min, x_max, y_max] = *region;
+                let inside = population
+                    .individuals
+                    .iter()
+                    .filter(|x| species.is_none_or(|s| s == x.species.id))
+                    .filter(|x| {
+                        x.x_coord >= x_min
+                            && x.x_coord < x_max
+                            && y_min <= x.y_coord
+                            && x.y_coord < y_max
+                    })
+                    .count();
+                let area = (x_max - x_min) * (y_max - y_min);
+                area > 0.0 && inside as f64 / area > *density
+            }
+        }
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Condition::CountBelow { species, count } => {
+                write!(f, "species {species} below {count} individuals")
+            }
+            Condition::CountAbove { species, count } => {
+                write!(f, "species {species} above {count} individuals")
+            }
+            Condition::DensityAbove {
+                region: [x_min, y_min, x_max, y_max],
+                species,
+                density,
+            } => {
+                match species {
+                    Some(id) => write!(f, "species {id}")?,
+                    None => write!(f, "all species")?,
+                }
+                write!(
+                    f,
+                    " denser than {density} in [{x_min}, {x_max}) x [{y_min}, {y_max})"
+                )
+            }
+        }
+    }
+}
+
+type Callback<'a> = Box<dyn FnMut(f64, &Condition) + 'a>;
+
+pub struct Watcher<'a> {
+    pub condition: Condition,
+    callback: Callback<'a>,
+    active: bool,
+}
+
+impl<'a> Population<'a> {
+    pub fn watch(&mut self, condition: Condition, callback: impl FnMut(f64, &Condition) + 'a) {
+        // Register a callback fired each time the condition goes from false to true
+
+        self.watchers.push(Watcher {
+            condition,
+            callback: Box::new(callback),
+            active: false,
+        });
+    }
+
+    pub fn check_watchers(&mut self) {
+        let mut watchers = std::mem::take(&mut self.watchers);
+        for watcher in watchers.iter_mut() {
+            let holds = watcher.condition.holds(self);
+            if holds && !watcher.active {
+                (watcher.callback)(self.t, &watcher.condition);
+            }
+            watcher.active = holds;
+        }
+        self.watchers = watchers;
+    }
+}
@@ -0,0 +1,18 @@
+// Reruns the golden cases and compares their event logs with the files under golden/.
+// After a change that is meant to alter behaviour, rewrite the logs with
+//
+//     BLESS=1 cargo test --test golden
+//
+// and commit them along with the change.
+
+use simulate::golden::{check_golden, golden_cases};
+
+#[test]
+fn event_logs_match_golden_files() {
+    let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/golden");
+    let bless = std::env::var_os("BLESS").is_some();
+    match check_golden(dir, bless) {
+        Ok(checked) => assert_eq!(checked, golden_cases().len()),
+        Err(e) => panic!("{e}"),
+    }
+}